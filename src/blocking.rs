@@ -0,0 +1,316 @@
+//! Blocking Client
+//!
+//! A synchronous facade over `MyBusTracker`, for applications which do not otherwise run a
+//! Tokio runtime. Requires the `blocking` feature.
+
+use std::future::Future;
+
+#[allow(deprecated)]
+use chrono::{Date, NaiveTime, Utc};
+#[cfg(feature = "chrono-tz")]
+use chrono::DateTime;
+use slog::Logger;
+use tokio::runtime::{Builder, Runtime};
+
+use super::{
+    models, BusTimesService, DisruptionsServices, MyBusTracker, MyBusTrackerBuilder,
+    MyBusTrackerError, TopologicalServices,
+};
+
+/// Blocking instance of the My Bus Tracker API.
+///
+/// Each method mirrors the equivalent method of the web service traits, but blocks until the
+/// request completes on a single-threaded runtime owned by this instance.
+pub struct BlockingMyBusTracker {
+    runtime: Runtime,
+    tracker: MyBusTracker,
+}
+
+impl BlockingMyBusTracker {
+    /// Create a new BlockingMyBusTracker instance.
+    ///
+    /// Requires an instance of a logger and your developer API key.
+    pub fn new(logger: &Logger, api_key: &str) -> Result<Self, MyBusTrackerError> {
+        Self::from_builder(
+            MyBusTracker::builder()
+                .logger(logger.clone())
+                .api_key(api_key),
+        )
+    }
+
+    /// Create a new BlockingMyBusTracker instance, which discards all log output (or, with the
+    /// `log` feature, sends it to the `log` facade).
+    pub fn without_logging(api_key: &str) -> Result<Self, MyBusTrackerError> {
+        Self::from_builder(MyBusTracker::builder().api_key(api_key))
+    }
+
+    /// Create a new BlockingMyBusTracker instance from a configured builder.
+    pub fn from_builder(builder: MyBusTrackerBuilder) -> Result<Self, MyBusTrackerError> {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| MyBusTrackerError::InternalError {
+                cause: e.to_string(),
+                source: Some(Box::new(e)),
+            })?;
+        let tracker = builder.build()?;
+        Ok(Self { runtime, tracker })
+    }
+
+    /// Drive the given future to completion on this instance's runtime.
+    fn run<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// See `MyBusTracker::get_raw`.
+    pub fn get_raw(
+        &self,
+        function: &str,
+        uri_params: Option<&str>,
+    ) -> Result<serde_json::Value, MyBusTrackerError> {
+        self.run(self.tracker.get_raw(function, uri_params))
+    }
+
+    /// See `TopologicalServices::get_topo_id`.
+    pub fn get_topo_id(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::TopoId, MyBusTrackerError> {
+        self.run(self.tracker.get_topo_id(operator))
+    }
+
+    /// See `TopologicalServices::get_services`.
+    pub fn get_services(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::Services, MyBusTrackerError> {
+        self.run(self.tracker.get_services(operator))
+    }
+
+    /// See `TopologicalServices::get_service_points`.
+    pub fn get_service_points(
+        &self,
+        service_reference: &str,
+        operator: &models::Operator,
+    ) -> Result<models::ServicePoints, MyBusTrackerError> {
+        self.run(self.tracker.get_service_points(service_reference, operator))
+    }
+
+    /// See `TopologicalServices::get_service_points_for`.
+    pub fn get_service_points_for(
+        &self,
+        service: &models::Service,
+    ) -> Result<models::ServicePoints, MyBusTrackerError> {
+        self.run(self.tracker.get_service_points_for(service))
+    }
+
+    /// See `TopologicalServices::get_service_points_batch`.
+    pub fn get_service_points_batch(
+        &self,
+        service_references: &[&str],
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> Result<Vec<models::ServicePoints>, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_service_points_batch(service_references, concurrency, operator),
+        )
+    }
+
+    /// See `TopologicalServices::get_destinations`.
+    pub fn get_destinations(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::Destinations, MyBusTrackerError> {
+        self.run(self.tracker.get_destinations(operator))
+    }
+
+    /// See `TopologicalServices::get_bus_stops`.
+    pub fn get_bus_stops(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::BusStops, MyBusTrackerError> {
+        self.run(self.tracker.get_bus_stops(operator))
+    }
+
+    /// See `TopologicalServices::get_topology`.
+    pub fn get_topology(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::Topology, MyBusTrackerError> {
+        self.run(self.tracker.get_topology(operator))
+    }
+
+    /// See `DisruptionsServices::get_disruptions`.
+    pub fn get_disruptions(
+        &self,
+        disruption_type: &Option<&models::DisruptionType>,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        self.run(self.tracker.get_disruptions(disruption_type, operator))
+    }
+
+    /// See `DisruptionsServices::get_disruptions_at_least`.
+    pub fn get_disruptions_at_least(
+        &self,
+        level: &models::DisruptionLevel,
+        disruption_type: &Option<&models::DisruptionType>,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_disruptions_at_least(level, disruption_type, operator),
+        )
+    }
+
+    /// See `DisruptionsServices::get_disruptions_for_service`.
+    pub fn get_disruptions_for_service(
+        &self,
+        service_reference: &str,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_disruptions_for_service(service_reference, operator),
+        )
+    }
+
+    /// See `DisruptionsServices::get_disruptions_for_stop`.
+    pub fn get_disruptions_for_stop(
+        &self,
+        stop_id: &models::StopId,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        self.run(self.tracker.get_disruptions_for_stop(stop_id, operator))
+    }
+
+    /// See `DisruptionsServices::get_diversions`.
+    #[allow(deprecated)]
+    pub fn get_diversions(
+        &self,
+        service_reference: &Option<&str>,
+        day: &Option<Date<Utc>>,
+        operator: &models::Operator,
+    ) -> Result<models::Diversions, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_diversions(service_reference, day, operator),
+        )
+    }
+
+    /// See `DisruptionsServices::get_diversions_for_services`.
+    #[allow(deprecated)]
+    pub fn get_diversions_for_services(
+        &self,
+        service_references: &[&str],
+        day: &Option<Date<Utc>>,
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> Result<models::Diversions, MyBusTrackerError> {
+        self.run(self.tracker.get_diversions_for_services(
+            service_references,
+            day,
+            concurrency,
+            operator,
+        ))
+    }
+
+    /// See `DisruptionsServices::get_diversion_points`.
+    pub fn get_diversion_points(
+        &self,
+        diversion: &str,
+        operator: &models::Operator,
+    ) -> Result<models::DiversionPoints, MyBusTrackerError> {
+        self.run(self.tracker.get_diversion_points(diversion, operator))
+    }
+
+    /// See `BusTimesService::get_bus_times`.
+    #[allow(deprecated)]
+    pub fn get_bus_times(
+        &self,
+        timetables: &[models::Timetable],
+        departure_count: u8,
+        departure_day: &Option<&Date<Utc>>,
+        departure_time: &Option<&NaiveTime>,
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
+        self.run(self.tracker.get_bus_times(
+            timetables,
+            departure_count,
+            departure_day,
+            departure_time,
+        ))
+    }
+
+    /// See `BusTimesService::get_bus_times_at`. Requires the `chrono-tz` feature.
+    #[cfg(feature = "chrono-tz")]
+    pub fn get_bus_times_at(
+        &self,
+        timetables: &[models::Timetable],
+        departure_count: u8,
+        departure: &Option<DateTime<Utc>>,
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_bus_times_at(timetables, departure_count, departure),
+        )
+    }
+
+    /// See `BusTimesService::get_bus_times_for_stop`.
+    pub fn get_bus_times_for_stop(
+        &self,
+        stop_id: &models::StopId,
+        departure_count: u8,
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_bus_times_for_stop(stop_id, departure_count),
+        )
+    }
+
+    /// See `BusTimesService::get_next_departure`.
+    pub fn get_next_departure(
+        &self,
+        stop_id: &models::StopId,
+    ) -> Result<Option<models::TimeData>, MyBusTrackerError> {
+        self.run(self.tracker.get_next_departure(stop_id))
+    }
+
+    /// See `BusTimesService::get_journey_times`.
+    #[allow(deprecated)]
+    pub fn get_journey_times(
+        &self,
+        stop_id: &Option<&models::StopId>,
+        journey_id: &models::JourneyIdentifier,
+        operator: &models::Operator,
+        day: &Date<Utc>,
+        mode: &models::JourneyTimeMode,
+    ) -> Result<models::JourneyTimes, MyBusTrackerError> {
+        self.run(
+            self.tracker
+                .get_journey_times(stop_id, journey_id, operator, day, mode),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::serve;
+
+    #[test]
+    fn requests_block_until_complete() {
+        // The server runs on its own runtime, while the tracker blocks on another.
+        let server_runtime = Runtime::new().unwrap();
+        let base_url = server_runtime.block_on(serve(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 42\r\nConnection: close\r\n\r\n\
+              {\"topoId\": \"20180101\", \"operatorId\": \"LB\"}",
+        ));
+        let tracker = BlockingMyBusTracker::from_builder(
+            MyBusTracker::builder().api_key("abc").base_url(base_url),
+        ).unwrap();
+
+        let topo_id = tracker.get_topo_id(&models::Operator::LothianBuses).unwrap();
+        assert_eq!(topo_id.topo_id, "20180101");
+        assert_eq!(topo_id.operator_id, models::Operator::LothianBuses);
+    }
+}
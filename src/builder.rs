@@ -0,0 +1,247 @@
+//! Builder for `MyBusTracker` instances
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper_util::client::legacy::connect::Connect;
+use slog::Logger;
+use url::Url;
+
+use super::{default_logger, default_user_agent, mask_key, models, new_client, new_connector,
+            ApiKey, Backend, Clock, HttpClient, MyBusTracker, MyBusTrackerError, ResponseFormat,
+            RetryPolicy, SystemClock, Transport, DEFAULT_ROOT_URL};
+
+/// Builder for a `MyBusTracker` instance.
+///
+/// An API key must be provided. Everything else is optional, and falls back
+/// to the same defaults as `MyBusTracker::new`:
+///   - the logger defaults to one which discards all output (or, with the `log` feature,
+///     sends it to the `log` facade);
+///   - the base URL defaults to the My Bus Tracker web service;
+///   - responses are requested as JSON;
+///   - the default operator is `Operator::AllOperators`;
+///   - identical concurrent requests are not coalesced;
+///   - requests are made with a new HTTP client;
+///   - there is no request timeout;
+///   - requests are not retried;
+///   - API keys are generated using the system clock;
+///   - the User-Agent identifies this crate and its version.
+#[derive(Default)]
+pub struct MyBusTrackerBuilder {
+    api_key: Option<String>,
+    logger: Option<Logger>,
+    base_url: Option<Url>,
+    timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    clock: Option<Box<dyn Clock>>,
+    user_agent: Option<String>,
+    response_format: ResponseFormat,
+    default_operator: Option<models::Operator>,
+    coalesce_requests: bool,
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl MyBusTrackerBuilder {
+    /// Create a new builder, with no configuration set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the developer API key.
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_owned());
+        self
+    }
+
+    /// Set the logger to use.
+    pub fn logger(mut self, logger: Logger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Set the base URL of the My Bus Tracker web service.
+    pub fn base_url(mut self, base_url: Url) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// Set the maximum time to wait for each request to complete.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry requests which fail with a transient error, up to `max_retries` times.
+    ///
+    /// See `MyBusTracker::set_retry`.
+    pub fn retry(mut self, max_retries: u32, base_delay: Duration) -> Self {
+        self.retry_policy = RetryPolicy::new(max_retries, base_delay);
+        self
+    }
+
+    /// Set the clock used to determine the current time when generating API keys.
+    pub fn clock<K: Clock + 'static>(mut self, clock: K) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Set the User-Agent sent with each request, e.g. to identify your application.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_owned());
+        self
+    }
+
+    /// Set the format in which the web service is asked to respond.
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = response_format;
+        self
+    }
+
+    /// Set the operator used by the `*_default` variants of the web service methods.
+    ///
+    /// See `MyBusTracker::set_default_operator`.
+    pub fn default_operator(mut self, operator: models::Operator) -> Self {
+        self.default_operator = Some(operator);
+        self
+    }
+
+    /// Set whether identical requests made concurrently should share a single HTTP request.
+    ///
+    /// See `MyBusTracker::set_coalesce_requests`.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> Self {
+        self.coalesce_requests = coalesce;
+        self
+    }
+
+    /// Set the transport through which requests are made, e.g. a `MockTransport` in tests.
+    ///
+    /// See `MyBusTracker::set_transport`.
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Build the `MyBusTracker` instance.
+    ///
+    /// Fails if the API key has not been set, or is empty.
+    pub fn build(self) -> Result<MyBusTracker, MyBusTrackerError> {
+        self.build_with(|| new_client(new_connector()))
+    }
+
+    /// Build the `MyBusTracker` instance, making requests with an existing HTTP client.
+    ///
+    /// Fails if the API key has not been set, or is empty.
+    pub fn build_with_client<C>(
+        self,
+        client: HttpClient<C>,
+    ) -> Result<MyBusTracker<C>, MyBusTrackerError>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        self.build_with(|| client)
+    }
+
+    /// Build the `MyBusTracker` instance, only creating its HTTP client with `client` if no
+    /// transport has been set.
+    fn build_with<C, F>(self, client: F) -> Result<MyBusTracker<C>, MyBusTrackerError>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+        F: FnOnce() -> HttpClient<C>,
+    {
+        let api_key = self
+            .api_key
+            .ok_or(MyBusTrackerError::MissingConfiguration { field: "api_key" })?;
+        let logger = self.logger.unwrap_or_else(default_logger);
+        trace!(logger, "Instantiating new MyBusTracker"; "api_key" => mask_key(&api_key));
+
+        let root_url = match self.base_url {
+            Some(base_url) => base_url,
+            None => Url::parse(DEFAULT_ROOT_URL)?,
+        };
+        let clock = self.clock.unwrap_or_else(|| Box::new(SystemClock));
+        let backend = match self.transport {
+            Some(transport) => Backend::Transport(transport),
+            None => Backend::Client(client()),
+        };
+
+        let mut tracker = MyBusTracker {
+            api_key: Arc::new(Mutex::new(ApiKey::new(&api_key, clock, &logger)?)),
+            logger,
+            backend,
+            root_url,
+            timeout: self.timeout,
+            retry_policy: self.retry_policy,
+            user_agent: self.user_agent.unwrap_or_else(default_user_agent),
+            response_format: self.response_format,
+            default_operator: self
+                .default_operator
+                .unwrap_or(models::Operator::AllOperators),
+            in_flight: None,
+            request_hook: None,
+            response_hook: None,
+        };
+        tracker.set_coalesce_requests(self.coalesce_requests);
+        Ok(tracker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+    use hyper_util::client::legacy::connect::HttpConnector;
+
+    struct FixedClock(DateTime<Utc>);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn build_with_only_an_api_key_uses_the_defaults() {
+        let tracker = MyBusTrackerBuilder::new().api_key("abc").build().unwrap();
+        assert_eq!(tracker.root_url.as_str(), DEFAULT_ROOT_URL);
+    }
+
+    #[test]
+    fn build_without_an_api_key_fails() {
+        let result = MyBusTrackerBuilder::new().build();
+        assert!(matches!(
+            result,
+            Err(MyBusTrackerError::MissingConfiguration { field: "api_key" })
+        ));
+    }
+
+    #[test]
+    fn build_with_every_optional_field() {
+        let base_url = Url::parse("http://localhost:8080/").unwrap();
+        let tracker = MyBusTrackerBuilder::new()
+            .api_key("abc")
+            .logger(Logger::root(slog::Discard, o!()))
+            .base_url(base_url.clone())
+            .build()
+            .unwrap();
+        assert_eq!(tracker.root_url, base_url);
+    }
+
+    #[test]
+    fn build_with_a_client_uses_the_configured_clock() {
+        let clock = FixedClock(Utc.with_ymd_and_hms(2018, 1, 2, 3, 45, 0).unwrap());
+        let tracker = MyBusTrackerBuilder::new()
+            .api_key("abc")
+            .clock(clock)
+            .build_with_client(new_client(HttpConnector::new()))
+            .unwrap();
+
+        let uri = tracker.get_uri("getTopoId", None).unwrap();
+        assert!(
+            uri.query()
+                .unwrap()
+                .contains("key=9558f0bfce3d0f9bdfa98b1e5102791c"),
+            "{}",
+            uri
+        );
+    }
+}
@@ -2,10 +2,18 @@
 //!
 //! For full documentation, see Section IV.4 of the My Bus Tracker API Guide (Version F)
 
-use hyper::{Method, Request};
-use super::{models, MyBusTracker, MyBusTrackerError};
-use futures::{self, Future};
+use super::{
+    models, MyBusTracker, MyBusTrackerError, MAX_DAYS_AHEAD, MAX_DEPARTURES, MAX_TIMETABLES,
+};
+use hyper_util::client::legacy::connect::Connect;
+use std::future::Future;
+// `chrono::Date` is deprecated upstream, but forms part of our public API.
+#[allow(deprecated)]
 use chrono::{Date, Duration, NaiveTime, Utc};
+#[cfg(feature = "chrono-tz")]
+use chrono::DateTime;
+#[cfg(feature = "chrono-tz")]
+use chrono_tz::Europe::London;
 
 /// Bus Times Web Service
 ///
@@ -19,38 +27,75 @@ pub trait BusTimesService {
     ///   - optionally, between 1 and 10 `departure_count`s, inclusive - the default is 2;
     ///   - optionally, a date, up to three-days in the future - the default is today;
     ///   - optionally, a time - the default is now.
+    #[allow(deprecated)]
     fn get_bus_times(
         &self,
         timetables: &[models::Timetable],
         departure_count: u8,
         departure_day: &Option<&Date<Utc>>,
         departure_time: &Option<&NaiveTime>,
-    ) -> Box<Future<Item = models::BusTimes, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::BusTimes, MyBusTrackerError>> + Send;
+
+    /// Get a list of timetables, departing from the given date and time.
+    ///
+    /// As `get_bus_times`, but taking the departure day and time together. The web service
+    /// works in Edinburgh local time, so the departure is converted to Europe/London time
+    /// before being split into a day and time. The departure must be within three days of
+    /// today; if not given, the default is now. Requires the `chrono-tz` feature.
+    #[cfg(feature = "chrono-tz")]
+    fn get_bus_times_at(
+        &self,
+        timetables: &[models::Timetable],
+        departure_count: u8,
+        departure: &Option<DateTime<Utc>>,
+    ) -> impl Future<Output = Result<models::BusTimes, MyBusTrackerError>> + Send;
+
+    /// Get a list of timetables for every service calling at the given stop
+    ///
+    /// You may request between 1 and 10 `departure_count`s, inclusive.
+    fn get_bus_times_for_stop(
+        &self,
+        stop_id: &models::StopId,
+        departure_count: u8,
+    ) -> impl Future<Output = Result<models::BusTimes, MyBusTrackerError>> + Send;
+
+    /// Get the soonest departure from the given stop, across all services
+    ///
+    /// Ties are broken by journey ID. Resolves to `None` if no departures are scheduled.
+    fn get_next_departure(
+        &self,
+        stop_id: &models::StopId,
+    ) -> impl Future<Output = Result<Option<models::TimeData>, MyBusTrackerError>> + Send;
 
     /// Get a list of bus arrival times
     ///
     /// You may request details on:
     ///   - a journey identifier, either a Journey ID or a Bus Fleet Number
     ///   - optionally, a specific stop - if the journey identifier is a Journey ID,
-    ///     this is not optional
+    ///     this is not optional, and omitting it fails with `MyBusTrackerError::MissingStopId`
+    #[allow(deprecated)]
     fn get_journey_times(
         &self,
-        stop_id: &Option<&str>,
+        stop_id: &Option<&models::StopId>,
         journey_id: &models::JourneyIdentifier,
         operator: &models::Operator,
         day: &Date<Utc>,
         mode: &models::JourneyTimeMode,
-    ) -> Box<Future<Item = models::JourneyTimes, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::JourneyTimes, MyBusTrackerError>> + Send;
 }
 
-impl BusTimesService for MyBusTracker {
-    fn get_bus_times(
+impl<C> BusTimesService for MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    #[allow(deprecated)]
+    async fn get_bus_times(
         &self,
         timetables: &[models::Timetable],
         departure_count: u8,
         departure_day: &Option<&Date<Utc>>,
         departure_time: &Option<&NaiveTime>,
-    ) -> Box<Future<Item = models::BusTimes, Error = MyBusTrackerError>> {
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting bus times";
@@ -59,73 +104,100 @@ impl BusTimesService for MyBusTracker {
             "departure_time" => ?departure_time,
             "departure_day" => ?departure_day,
         );
-        if timetables.len() > 5 {
-            return Box::new(futures::failed(MyBusTrackerError::TooManyTimetables));
-        }
-
-        if departure_count > 10 {
-            return Box::new(futures::failed(MyBusTrackerError::TooManyDepartures));
-        }
-
         let day_difference: Duration = match *departure_day {
             Some(departure_day) => departure_day.signed_duration_since(Utc::today()),
             None => Duration::days(0),
         };
 
-        if day_difference > Duration::days(3) || day_difference < Duration::days(0) {
-            return Box::new(futures::failed(MyBusTrackerError::DateOutOfBounds));
+        self.get_bus_times_on_day(
+            timetables,
+            departure_count,
+            day_difference.num_days(),
+            departure_time,
+        )
+        .await
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    async fn get_bus_times_at(
+        &self,
+        timetables: &[models::Timetable],
+        departure_count: u8,
+        departure: &Option<DateTime<Utc>>,
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
+        match *departure {
+            Some(departure) => {
+                // The day is counted from today in Edinburgh, by the tracker's clock, which
+                // shortly before midnight UTC in summer is already a day ahead.
+                let local = departure.with_timezone(&London);
+                let local_today = self.now()?.with_timezone(&London).date_naive();
+                let days_offset = (local.date_naive() - local_today).num_days();
+                let time = local.time();
+                self.get_bus_times_on_day(timetables, departure_count, days_offset, &Some(&time))
+                    .await
+            }
+            None => {
+                self.get_bus_times(timetables, departure_count, &None, &None)
+                    .await
+            }
         }
+    }
 
-        let departure_time_string = match *departure_time {
-            Some(time) => format!("&time={}", time.format("%H:%M")),
-            None => String::new(),
-        };
+    async fn get_bus_times_for_stop(
+        &self,
+        stop_id: &models::StopId,
+        departure_count: u8,
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
+        debug!(
+            self.logger,
+            "Getting bus times for stop";
+            "stop_id" => %stop_id,
+            "departures" => departure_count,
+        );
+        if departure_count > MAX_DEPARTURES {
+            return Err(MyBusTrackerError::TooManyDepartures {
+                requested: departure_count,
+                max: MAX_DEPARTURES,
+            });
+        }
 
-        let time_requests = timetables
-            .iter()
-            .enumerate()
-            .map(|(i, item)| {
-                format!(
-                    "stopId{0}={1}&refService{0}={2}&refDest{0}={3}",
-                    i + 1,
-                    item.stop_id,
-                    item.service_reference,
-                    item.destination_reference
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("&");
+        let uri_params = format!("stopId1={}&nb={}", stop_id, departure_count);
+        let uri = self.get_uri("getBusTimes", Some(&uri_params))?;
 
-        let uri_params = format!(
-            "{}&nb={}&day={}{}",
-            time_requests,
-            departure_count,
-            day_difference.num_days(),
-            departure_time_string
-        );
-        let uri = match self.get_uri("getBusTimes", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        self.make_request(uri).await
+    }
 
-        let request = Request::new(Method::Get, uri);
+    async fn get_next_departure(
+        &self,
+        stop_id: &models::StopId,
+    ) -> Result<Option<models::TimeData>, MyBusTrackerError> {
+        let bus_times = self.get_bus_times_for_stop(stop_id, 1).await?;
 
-        self.make_request(request)
+        Ok(bus_times
+            .bus_times
+            .into_iter()
+            .flat_map(|bus_time| bus_time.times)
+            .min_by(|a, b| {
+                a.minutes
+                    .cmp(&b.minutes)
+                    .then_with(|| a.journey_id.cmp(&b.journey_id))
+            }))
     }
 
-    fn get_journey_times(
+    #[allow(deprecated)]
+    async fn get_journey_times(
         &self,
-        stop_id: &Option<&str>,
+        stop_id: &Option<&models::StopId>,
         journey_id: &models::JourneyIdentifier,
         operator: &models::Operator,
         day: &Date<Utc>,
         mode: &models::JourneyTimeMode,
-    ) -> Box<Future<Item = models::JourneyTimes, Error = MyBusTrackerError>> {
+    ) -> Result<models::JourneyTimes, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting journey times";
             "journey_id" => ?journey_id,
-            "stop_id" => stop_id,
+            "stop_id" => ?stop_id,
             "operator" => ?operator,
             "day" => ?day,
             "mode" => ?mode,
@@ -136,14 +208,23 @@ impl BusTimesService for MyBusTracker {
             None => String::new(),
         };
 
+        if let (None, models::JourneyIdentifier::JourneyId(_)) = (stop_id, journey_id) {
+            return Err(MyBusTrackerError::MissingStopId);
+        }
+
         let journey_id_string = match *journey_id {
             models::JourneyIdentifier::JourneyId(ref journey) => format!("journeyId={}&", journey),
             models::JourneyIdentifier::BusId(ref bus) => format!("busId={}&", bus),
         };
 
         let day_difference: Duration = day.signed_duration_since(Utc::today());
-        if day_difference > Duration::days(3) || day_difference < Duration::days(0) {
-            return Box::new(futures::failed(MyBusTrackerError::DateOutOfBounds));
+        if day_difference > Duration::days(MAX_DAYS_AHEAD)
+            || day_difference < Duration::days(0)
+        {
+            return Err(MyBusTrackerError::DateOutOfBounds {
+                days_offset: day_difference.num_days(),
+                max: MAX_DAYS_AHEAD,
+            });
         }
 
         let uri_params = format!(
@@ -155,13 +236,470 @@ impl BusTimesService for MyBusTracker {
             mode
         );
 
-        let uri = match self.get_uri("getJourneyTimes", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
+        let uri = self.get_uri("getJourneyTimes", Some(&uri_params))?;
+
+        self.make_request(uri).await
+    }
+}
+
+/// Variants of the Bus Times Web Service methods, using the instance's default operator.
+///
+/// See `MyBusTracker::set_default_operator`.
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// See `BusTimesService::get_journey_times`.
+    #[allow(deprecated)]
+    pub async fn get_journey_times_default(
+        &self,
+        stop_id: &Option<&models::StopId>,
+        journey_id: &models::JourneyIdentifier,
+        day: &Date<Utc>,
+        mode: &models::JourneyTimeMode,
+    ) -> Result<models::JourneyTimes, MyBusTrackerError> {
+        self.get_journey_times(stop_id, journey_id, &self.default_operator, day, mode)
+            .await
+    }
+}
+
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Get a list of timetables, departing `days_offset` days after today, as
+    /// `BusTimesService::get_bus_times`.
+    async fn get_bus_times_on_day(
+        &self,
+        timetables: &[models::Timetable],
+        departure_count: u8,
+        days_offset: i64,
+        departure_time: &Option<&NaiveTime>,
+    ) -> Result<models::BusTimes, MyBusTrackerError> {
+        if timetables.is_empty() {
+            return Err(MyBusTrackerError::NoTimetablesRequested);
+        }
+        if timetables.len() > MAX_TIMETABLES {
+            return Err(MyBusTrackerError::TooManyTimetables {
+                requested: timetables.len(),
+                max: MAX_TIMETABLES,
+            });
+        }
+
+        if departure_count > MAX_DEPARTURES {
+            return Err(MyBusTrackerError::TooManyDepartures {
+                requested: departure_count,
+                max: MAX_DEPARTURES,
+            });
+        }
+
+        if !(0..=MAX_DAYS_AHEAD).contains(&days_offset) {
+            return Err(MyBusTrackerError::DateOutOfBounds {
+                days_offset,
+                max: MAX_DAYS_AHEAD,
+            });
+        }
+
+        let departure_time_string = match *departure_time {
+            Some(time) => format!("&time={}", time.format("%H:%M")),
+            None => String::new(),
         };
 
-        let request = Request::new(Method::Get, uri);
+        let time_requests = timetables
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let mut request = format!("stopId{}={}", i + 1, item.stop_id);
+                // Stop-only timetables (see `Timetable::for_stop`) omit the empty references.
+                if !item.service_reference.is_empty() {
+                    request.push_str(&format!("&refService{}={}", i + 1, item.service_reference));
+                }
+                if !item.destination_reference.is_empty() {
+                    request.push_str(&format!("&refDest{}={}", i + 1, item.destination_reference));
+                }
+                request
+            })
+            .collect::<Vec<String>>()
+            .join("&");
+
+        let uri_params = format!(
+            "{}&nb={}&day={}{}",
+            time_requests,
+            departure_count,
+            days_offset,
+            departure_time_string
+        );
+        let uri = self.get_uri("getBusTimes", Some(&uri_params))?;
+
+        self.make_request(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{serve, serve_json};
+    use hyper::Uri;
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    const BUS_TIMES: &str = r#"{"busTimes": [
+        {"operatorId": "LB", "stopId": "36232626", "stopName": "Princes Street",
+         "refService": "26", "mnemoService": "26", "nameService": "Clerwood - Seton Sands",
+         "refDest": null, "nameDest": null,
+         "timeDatas": [
+             {"day": 0, "time": "14:41", "minutes": 9, "reliability": "H", "type": "N",
+              "terminus": "Seton Sands", "journeyId": "2601", "busId": null},
+             {"day": 0, "time": "14:36", "minutes": 4, "reliability": "T", "type": "N",
+              "terminus": "Seton Sands", "journeyId": "2602", "busId": null}
+         ]},
+        {"operatorId": "LB", "stopId": "36232626", "stopName": "Princes Street",
+         "refService": "22", "mnemoService": "22", "nameService": "Gyle - Ocean Terminal",
+         "refDest": null, "nameDest": null,
+         "timeDatas": [
+             {"day": 0, "time": "14:36", "minutes": 4, "reliability": "H", "type": "N",
+              "terminus": "Ocean Terminal", "journeyId": "2201", "busId": "123"}
+         ]}
+    ]}"#;
+
+    /// A tracker for the given base URL, and the URIs of the requests it makes.
+    fn tracker(base_url: Url) -> (MyBusTracker, Arc<Mutex<Vec<Uri>>>) {
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(base_url)
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |uri| recorded.lock().unwrap().push(uri.clone()));
+        (tracker, requests)
+    }
+
+    #[tokio::test]
+    async fn requesting_no_timetables_fails_without_a_request() {
+        let (tracker, requests) = tracker(Url::parse("http://localhost:8080/").unwrap());
+        let error = tracker
+            .get_bus_times(&[], 2, &None, &None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, MyBusTrackerError::NoTimetablesRequested), "{:?}", error);
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn journey_ids_require_a_stop_but_bus_ids_do_not() {
+        let base_url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 20\r\nConnection: close\r\n\r\n\
+                               {\"journeyTimes\": []}")
+            .await;
+        let (tracker, requests) = tracker(base_url);
+        let today = Utc::today();
+        let mode = models::JourneyTimeMode::All;
+        let operator = models::Operator::LothianBuses;
+
+        let journey_id = models::JourneyIdentifier::JourneyId("1234".to_owned());
+        let error = tracker
+            .get_journey_times(&None, &journey_id, &operator, &today, &mode)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, MyBusTrackerError::MissingStopId), "{:?}", error);
+        assert!(requests.lock().unwrap().is_empty());
+
+        let bus_id = models::JourneyIdentifier::BusId("567".to_owned());
+        tracker
+            .get_journey_times(&None, &bus_id, &operator, &today, &mode)
+            .await
+            .unwrap();
+        let uri = &requests.lock().unwrap()[0];
+        assert!(uri.query().unwrap().contains("&busId=567&"), "{}", uri);
+        assert!(!uri.query().unwrap().contains("stopId="), "{}", uri);
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    #[tokio::test]
+    async fn departures_are_requested_in_local_time() {
+        use crate::tests::TestClock;
+        use chrono::TimeZone;
+
+        let base_url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 16\r\nConnection: close\r\n\r\n\
+                               {\"busTimes\": []}")
+            .await;
+        // 23:30 UTC in July is already 00:30 the next day in Edinburgh.
+        let now = Utc.with_ymd_and_hms(2018, 7, 10, 23, 30, 0).unwrap();
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(base_url)
+            .clock(TestClock::at(now))
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |uri| recorded.lock().unwrap().push(uri.clone()));
+        let timetable =
+            models::Timetable::new("36232626".into(), "26", "26A", models::Operator::LothianBuses)
+                .unwrap();
+
+        tracker
+            .get_bus_times_at(&[timetable], 2, &Some(now + Duration::minutes(15)))
+            .await
+            .unwrap();
+
+        let uri = &requests.lock().unwrap()[0];
+        let query = uri.query().unwrap();
+        assert!(query.ends_with("&day=0&time=00:45"), "{}", query);
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    #[tokio::test]
+    async fn departure_days_are_counted_from_the_trackers_clock() {
+        use crate::tests::TestClock;
+        use chrono::TimeZone;
+
+        let base_url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 16\r\nConnection: close\r\n\r\n\
+                               {\"busTimes\": []}")
+            .await;
+        // By the tracker's clock it is already 11th July in Edinburgh, whatever the system says.
+        let now = Utc.with_ymd_and_hms(2018, 7, 10, 23, 30, 0).unwrap();
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(base_url)
+            .clock(TestClock::at(now))
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |uri| recorded.lock().unwrap().push(uri.clone()));
+        let timetables =
+            [models::Timetable::new("36232626".into(), "26", "26A", models::Operator::LothianBuses)
+                .unwrap()];
+
+        let departure = Utc.with_ymd_and_hms(2018, 7, 12, 8, 0, 0).unwrap();
+        tracker
+            .get_bus_times_at(&timetables, 2, &Some(departure))
+            .await
+            .unwrap();
+
+        let query = requests.lock().unwrap()[0].query().unwrap().to_owned();
+        assert!(query.ends_with("&day=1&time=09:00"), "{}", query);
 
-        self.make_request(request)
+        let too_late = Utc.with_ymd_and_hms(2018, 7, 15, 8, 0, 0).unwrap();
+        match tracker.get_bus_times_at(&timetables, 2, &Some(too_late)).await {
+            Err(MyBusTrackerError::DateOutOfBounds { days_offset, max }) => {
+                assert_eq!((days_offset, max), (4, MAX_DAYS_AHEAD));
+            }
+            other => panic!("Expected DateOutOfBounds, got {:?}", other),
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn times_for_a_stop_are_requested_by_stop_alone() {
+        let (tracker, requests) = tracker(serve_json(BUS_TIMES).await);
+        let stop_id = models::StopId::from("36232626");
+
+        let bus_times = tracker.get_bus_times_for_stop(&stop_id, 3).await.unwrap();
+
+        assert_eq!(bus_times.bus_times.len(), 2);
+        let uri = &requests.lock().unwrap()[0];
+        assert!(
+            uri.query().unwrap().ends_with("&function=getBusTimes&stopId1=36232626&nb=3"),
+            "{}",
+            uri
+        );
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn next_reference_journey_times_give_the_next_reference_stop() {
+        let response = r#"{"journeyTimes": [{
+            "journeyId": "1234", "busId": "567", "operatorId": "LB",
+            "refService": "26", "mnemoService": "26", "nameService": "Clerwood - Seton Sands",
+            "refDest": "26A", "nameDest": "Seton Sands",
+            "journeyTimeDatas": [
+                {"order": 1, "stopId": "36232626", "stopName": "Princes Street", "day": 0,
+                 "time": "14:25", "minutes": -2, "reliability": "H", "type": "R",
+                 "busStopDisruption": false},
+                {"order": 2, "stopId": "36232627", "stopName": "York Place", "day": 0,
+                 "time": "14:30", "minutes": 3, "reliability": "H", "type": "N",
+                 "busStopDisruption": false},
+                {"order": 3, "stopId": "36232628", "stopName": "Leith Walk", "day": 0,
+                 "time": "14:40", "minutes": 13, "reliability": "H", "type": "R",
+                 "busStopDisruption": false}
+            ],
+            "globalDisruption": false, "serviceDisruption": false, "serviceDiversion": false
+        }]}"#;
+        let (tracker, requests) = tracker(serve_json(response).await);
+        let bus_id = models::JourneyIdentifier::BusId("567".to_owned());
+
+        let journey_times = tracker
+            .get_journey_times(
+                &None,
+                &bus_id,
+                &models::Operator::LothianBuses,
+                &Utc::today(),
+                &models::JourneyTimeMode::NextReference,
+            )
+            .await
+            .unwrap();
+
+        let uri = &requests.lock().unwrap()[0];
+        assert!(uri.query().unwrap().ends_with("&mode=1"), "{}", uri);
+        let next = journey_times.next_reference().unwrap();
+        assert_eq!(next.order, 3);
+        assert_eq!(next.stop_name, "Leith Walk");
+    }
+
+    #[tokio::test]
+    async fn stop_only_timetables_omit_the_empty_references() {
+        let (tracker, requests) = tracker(serve_json(r#"{"busTimes": []}"#).await);
+        let timetables = [
+            models::Timetable::new("36232626".into(), "26", "26A", models::Operator::LothianBuses)
+                .unwrap(),
+            models::Timetable::for_stop("36232627".into(), models::Operator::LothianBuses)
+                .unwrap(),
+        ];
+
+        tracker
+            .get_bus_times(&timetables, 2, &None, &None)
+            .await
+            .unwrap();
+
+        let uri = &requests.lock().unwrap()[0];
+        let query = uri.query().unwrap();
+        assert!(
+            query.contains("&stopId1=36232626&refService1=26&refDest1=26A&stopId2=36232627&nb=2&"),
+            "{}",
+            query
+        );
+    }
+
+    #[tokio::test]
+    async fn next_departure_is_the_soonest_of_any_service() {
+        let (tracker, _) = tracker(serve_json(BUS_TIMES).await);
+        let stop_id = models::StopId::from("36232626");
+
+        let next = tracker
+            .get_next_departure(&stop_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Two departures are due in 4 minutes, so the lower journey ID is chosen.
+        assert_eq!(next.minutes, 4);
+        assert_eq!(next.journey_id, "2201");
+    }
+
+    #[tokio::test]
+    async fn next_departure_is_none_without_any_departures() {
+        let (tracker, _) = tracker(serve_json(r#"{"busTimes": []}"#).await);
+        let stop_id = models::StopId::from("36232626");
+
+        let next = tracker.get_next_departure(&stop_id).await.unwrap();
+        assert!(next.is_none());
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn requests_up_to_the_limits_are_made() {
+        let (tracker, requests) = tracker(serve_json(r#"{"busTimes": []}"#).await);
+        let timetable =
+            models::Timetable::new("36232626".into(), "26", "26A", models::Operator::LothianBuses)
+                .unwrap();
+        let last_day = Utc::today() + Duration::days(MAX_DAYS_AHEAD);
+
+        let timetables = vec![timetable; MAX_TIMETABLES + 1];
+        tracker
+            .get_bus_times(&timetables[..MAX_TIMETABLES], MAX_DEPARTURES, &Some(&last_day), &None)
+            .await
+            .unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let too_many_timetables = tracker.get_bus_times(&timetables, 2, &None, &None).await;
+        let too_many_departures = tracker
+            .get_bus_times(&timetables[..1], MAX_DEPARTURES + 1, &None, &None)
+            .await;
+        let too_late = tracker
+            .get_bus_times(&timetables[..1], 2, &Some(&last_day.succ_opt().unwrap()), &None)
+            .await;
+        assert!(too_many_timetables.is_err());
+        assert!(too_many_departures.is_err());
+        assert!(too_late.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn limit_errors_report_the_requested_count() {
+        let (tracker, _) = tracker(Url::parse("http://localhost:8080/").unwrap());
+        let timetable =
+            models::Timetable::new("36232626".into(), "26", "26A", models::Operator::LothianBuses)
+                .unwrap();
+
+        let error = tracker
+            .get_bus_times(&vec![timetable.clone(); 6], 2, &None, &None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, MyBusTrackerError::TooManyTimetables { requested: 6, max: 5 }),
+            "{:?}",
+            error
+        );
+        assert_eq!(error.to_string(), "6 timetables requested (max 5)");
+
+        let error = tracker
+            .get_bus_times(&[timetable], 11, &None, &None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "11 departures requested (max 10)");
+
+        let error = tracker
+            .get_bus_times_for_stop(&"36232626".into(), 12)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, MyBusTrackerError::TooManyDepartures { requested: 12, max: 10 }),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn out_of_bounds_dates_report_the_offset() {
+        let (tracker, requests) = tracker(Url::parse("http://localhost:8080/").unwrap());
+        let timetable =
+            models::Timetable::new("36232626".into(), "26", "26A", models::Operator::LothianBuses)
+                .unwrap();
+        let yesterday = Utc::today() - Duration::days(1);
+        let four_days = Utc::today() + Duration::days(4);
+
+        let error = tracker
+            .get_bus_times(&[timetable], 2, &Some(&four_days), &None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, MyBusTrackerError::DateOutOfBounds { days_offset: 4, max: 3 }),
+            "{:?}",
+            error
+        );
+        assert_eq!(error.to_string(), "Date out of bounds: requested 4 days ahead (max 3)");
+
+        let bus_id = models::JourneyIdentifier::BusId("567".to_owned());
+        let error = tracker
+            .get_journey_times(
+                &None,
+                &bus_id,
+                &models::Operator::LothianBuses,
+                &yesterday,
+                &models::JourneyTimeMode::All,
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, MyBusTrackerError::DateOutOfBounds { days_offset: -1, max: 3 }),
+            "{:?}",
+            error
+        );
+        assert!(requests.lock().unwrap().is_empty());
     }
 }
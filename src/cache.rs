@@ -0,0 +1,226 @@
+//! Topology Cache
+//!
+//! Services, destinations and bus stops only change when the network topology does, which is
+//! at most once per day. `CachedMyBusTracker` keeps these responses, and only fetches them
+//! afresh once the topology ID changes.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard};
+
+use hyper_util::client::legacy::connect::Connect;
+
+use super::{models, Connector, MyBusTracker, MyBusTrackerError, TopologicalServices};
+
+/// Instance of the My Bus Tracker API, caching topological data.
+///
+/// Each call to `get_services`, `get_destinations` or `get_bus_stops` first (cheaply) requests
+/// the current topology ID. The full response is only requested if it hasn't been cached for
+/// that topology ID. Requests for the topology ID itself and for service points are passed
+/// straight through to the wrapped instance; use `inner` for all other web services.
+pub struct CachedMyBusTracker<C = Connector> {
+    tracker: MyBusTracker<C>,
+    cache: Mutex<Cache>,
+}
+
+/// Cached topological data, by operator.
+type Cache = HashMap<models::Operator, Topology>;
+
+/// The cached topological data of a single operator.
+#[derive(Default)]
+struct Topology {
+    topo_id: String,
+    services: Option<models::Services>,
+    destinations: Option<models::Destinations>,
+    bus_stops: Option<models::BusStops>,
+}
+
+impl<C> CachedMyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Wrap a MyBusTracker instance, caching its topological data.
+    pub fn new(tracker: MyBusTracker<C>) -> Self {
+        Self {
+            tracker,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The wrapped MyBusTracker instance, e.g. to call other web services.
+    pub fn inner(&self) -> &MyBusTracker<C> {
+        &self.tracker
+    }
+
+    /// Discard all cached data.
+    pub fn invalidate(&self) -> Result<(), MyBusTrackerError> {
+        self.lock()?.clear();
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Cache>, MyBusTrackerError> {
+        self.cache
+            .lock()
+            .map_err(|e| MyBusTrackerError::InternalError {
+                cause: e.to_string(),
+                source: None,
+            })
+    }
+
+    /// Return the cached value in the given slot if it is for the current topology, fetching
+    /// and caching it otherwise.
+    async fn cached<T, F, Fut>(
+        &self,
+        operator: &models::Operator,
+        slot: fn(&mut Topology) -> &mut Option<T>,
+        fetch: F,
+    ) -> Result<T, MyBusTrackerError>
+    where
+        T: Clone,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, MyBusTrackerError>>,
+    {
+        let topo_id = self.tracker.get_topo_id(operator).await?.topo_id;
+
+        {
+            let mut cache = self.lock()?;
+            let topology = cache.entry(operator.clone()).or_default();
+            if topology.topo_id != topo_id {
+                debug!(self.tracker.logger, "Topology changed; discarding cached data";
+                       "operator" => %operator, "topo_id" => &topo_id);
+                *topology = Topology {
+                    topo_id: topo_id.clone(),
+                    ..Topology::default()
+                };
+            }
+            if let Some(value) = slot(topology) {
+                trace!(self.tracker.logger, "Using cached topological data";
+                       "operator" => %operator);
+                return Ok(value.clone());
+            }
+        }
+
+        let value = fetch().await?;
+
+        let mut cache = self.lock()?;
+        let topology = cache.entry(operator.clone()).or_default();
+        // The topology may have changed again while fetching, in which case this value is stale.
+        if topology.topo_id == topo_id {
+            *slot(topology) = Some(value.clone());
+        }
+        Ok(value)
+    }
+}
+
+impl<C> TopologicalServices for CachedMyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    async fn get_topo_id(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::TopoId, MyBusTrackerError> {
+        self.tracker.get_topo_id(operator).await
+    }
+
+    async fn get_services(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::Services, MyBusTrackerError> {
+        self.cached(
+            operator,
+            |topology| &mut topology.services,
+            || self.tracker.get_services(operator),
+        ).await
+    }
+
+    async fn get_service_points(
+        &self,
+        service_reference: &str,
+        operator: &models::Operator,
+    ) -> Result<models::ServicePoints, MyBusTrackerError> {
+        self.tracker
+            .get_service_points(service_reference, operator)
+            .await
+    }
+
+    async fn get_service_points_batch(
+        &self,
+        service_references: &[&str],
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> Result<Vec<models::ServicePoints>, MyBusTrackerError> {
+        self.tracker
+            .get_service_points_batch(service_references, concurrency, operator)
+            .await
+    }
+
+    async fn get_destinations(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::Destinations, MyBusTrackerError> {
+        self.cached(
+            operator,
+            |topology| &mut topology.destinations,
+            || self.tracker.get_destinations(operator),
+        ).await
+    }
+
+    async fn get_bus_stops(
+        &self,
+        operator: &models::Operator,
+    ) -> Result<models::BusStops, MyBusTrackerError> {
+        self.cached(
+            operator,
+            |topology| &mut topology.bus_stops,
+            || self.tracker.get_bus_stops(operator),
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::tests::serve_json;
+
+    /// Both the topology ID and the services, so that one server answers either request.
+    const TOPOLOGY: &str = r#"{"topoId": "20180101", "operatorId": "LB", "services": [
+        {"ref": "26", "operatorId": "LB", "mnemo": "26", "name": "Clerwood - Seton Sands",
+         "dests": ["26A", "26B"]}
+    ]}"#;
+
+    #[tokio::test]
+    async fn services_are_fetched_once_per_topology() {
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(serve_json(TOPOLOGY).await)
+            .build()
+            .unwrap();
+        let functions = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&functions);
+        tracker.on_request(move |uri| {
+            let query = uri.query().unwrap_or("").to_owned();
+            let function = url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "function")
+                .map(|(_, function)| function.into_owned())
+                .unwrap_or_default();
+            recorded.lock().unwrap().push(function);
+        });
+        let cached = CachedMyBusTracker::new(tracker);
+        let operator = models::Operator::LothianBuses;
+
+        let first = cached.get_services(&operator).await.unwrap();
+        let second = cached.get_services(&operator).await.unwrap();
+
+        assert_eq!(first.services.len(), 1);
+        assert_eq!(second.services.len(), 1);
+        assert_eq!(*functions.lock().unwrap(), ["getTopoId", "getServices", "getTopoId"]);
+
+        cached.invalidate().unwrap();
+        cached.get_services(&operator).await.unwrap();
+        let services = functions.lock().unwrap().iter().filter(|f| *f == "getServices").count();
+        assert_eq!(services, 2);
+    }
+}
@@ -0,0 +1,22 @@
+//! Clocks
+//!
+//! API keys are derived from the current time, so the source of that time is abstracted to
+//! allow a fixed time to be used instead of the system clock.
+
+use chrono::{DateTime, Utc};
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A clock which reads the (UTC) system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
@@ -0,0 +1,178 @@
+//! Request Coalescing
+//!
+//! Identical requests made concurrently, e.g. by several components of an application within
+//! the same tick, may share a single HTTP request: the first caller makes the request, and any
+//! others await its result. This is opt-in; see `MyBusTracker::set_coalesce_requests`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use hyper::Uri;
+
+use super::MyBusTrackerError;
+
+/// Requests in flight, by URI.
+pub type InFlight = Mutex<Requests>;
+
+type Requests = HashMap<Uri, SharedFetch>;
+
+/// A request whose result may be awaited by several callers.
+type SharedFetch = Shared<BoxFuture<'static, Result<Arc<Vec<u8>>, Arc<MyBusTrackerError>>>>;
+
+/// Performs a single HTTP GET request for the given URI, or awaits an identical request which
+/// is already in flight, returning the response body.
+///
+/// `request` is only called if there is no identical request in flight. Callers sharing a
+/// failed request each receive a copy of the error.
+pub async fn fetch<F>(
+    in_flight: &Arc<InFlight>,
+    uri: Uri,
+    request: F,
+) -> Result<Vec<u8>, MyBusTrackerError>
+where
+    F: FnOnce() -> BoxFuture<'static, Result<Vec<u8>, MyBusTrackerError>>,
+{
+    let request = lock(in_flight)?
+        .entry(uri.clone())
+        .or_insert_with(|| new_request(Arc::downgrade(in_flight), uri, request()))
+        .clone();
+
+    match request.await {
+        Ok(body) => Ok(Arc::try_unwrap(body).unwrap_or_else(|body| (*body).clone())),
+        Err(error) => Err(Arc::try_unwrap(error).unwrap_or_else(copy_error)),
+    }
+}
+
+/// A new shared request, which removes itself from the requests in flight once complete.
+///
+/// The request only holds a weak reference to the requests in flight, as it is itself held
+/// there.
+fn new_request(
+    in_flight: Weak<InFlight>,
+    uri: Uri,
+    request: BoxFuture<'static, Result<Vec<u8>, MyBusTrackerError>>,
+) -> SharedFetch {
+    let request = async move {
+        let result = request.await;
+        if let Some(in_flight) = in_flight.upgrade() {
+            if let Ok(mut in_flight) = in_flight.lock() {
+                in_flight.remove(&uri);
+            }
+        }
+        result.map(Arc::new).map_err(Arc::new)
+    };
+    request.boxed().shared()
+}
+
+/// A copy of an error shared between several callers, keeping the original as its source.
+fn copy_error(error: Arc<MyBusTrackerError>) -> MyBusTrackerError {
+    match *error {
+        MyBusTrackerError::Timeout => MyBusTrackerError::Timeout,
+        MyBusTrackerError::CommunicationError {
+            ref cause,
+            status,
+            ref uri,
+            ..
+        } => MyBusTrackerError::CommunicationError {
+            cause: cause.clone(),
+            status,
+            uri: uri.clone(),
+            source: Some(Box::new(Arc::clone(&error))),
+        },
+        _ => MyBusTrackerError::InternalError {
+            cause: error.to_string(),
+            source: Some(Box::new(Arc::clone(&error))),
+        },
+    }
+}
+
+fn lock(in_flight: &InFlight) -> Result<MutexGuard<'_, Requests>, MyBusTrackerError> {
+    in_flight
+        .lock()
+        .map_err(|e| MyBusTrackerError::InternalError {
+            cause: e.to_string(),
+            source: None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures_util::future;
+
+    use super::*;
+
+    /// A request which completes with the given result after yielding once, counting each
+    /// request made.
+    fn request(
+        requests: &Arc<AtomicUsize>,
+        result: Result<Vec<u8>, MyBusTrackerError>,
+    ) -> impl FnOnce() -> BoxFuture<'static, Result<Vec<u8>, MyBusTrackerError>> {
+        let requests = Arc::clone(requests);
+        move || {
+            requests.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::task::yield_now().await;
+                result
+            }
+            .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_identical_requests_share_one_request() {
+        let in_flight = Arc::new(InFlight::default());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let uri: Uri = "http://localhost/?function=getTopoId".parse().unwrap();
+        let other: Uri = "http://localhost/?function=getServices".parse().unwrap();
+
+        let (first, second, third) = future::join3(
+            fetch(&in_flight, uri.clone(), request(&requests, Ok(b"1".to_vec()))),
+            fetch(&in_flight, uri.clone(), request(&requests, Ok(b"2".to_vec()))),
+            fetch(&in_flight, other, request(&requests, Ok(b"3".to_vec()))),
+        )
+        .await;
+
+        assert_eq!(first.unwrap(), b"1");
+        assert_eq!(second.unwrap(), b"1");
+        assert_eq!(third.unwrap(), b"3");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(in_flight.lock().unwrap().is_empty());
+
+        let later = fetch(&in_flight, uri, request(&requests, Ok(b"4".to_vec()))).await;
+        assert_eq!(later.unwrap(), b"4");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn callers_sharing_a_failed_request_each_receive_the_error() {
+        let in_flight = Arc::new(InFlight::default());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let uri: Uri = "http://localhost/?function=getTopoId".parse().unwrap();
+        let error = MyBusTrackerError::CommunicationError {
+            cause: "HTTP 503".to_owned(),
+            status: Some(503),
+            uri: None,
+            source: None,
+        };
+
+        let (first, second) = future::join(
+            fetch(&in_flight, uri.clone(), request(&requests, Err(error))),
+            fetch(&in_flight, uri, request(&requests, Ok(Vec::new()))),
+        )
+        .await;
+
+        for result in [first, second] {
+            let error = result.unwrap_err();
+            assert!(
+                matches!(error, MyBusTrackerError::CommunicationError { status: Some(503), .. }),
+                "{:?}",
+                error
+            );
+            assert!(error.is_retryable());
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,83 @@
+//! Response Compression
+//!
+//! Requests advertise support for gzip and deflate compressed responses, which are
+//! decompressed before deserialization. Requires the `compression` feature.
+
+use std::io::Read;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use hyper::header::HeaderValue;
+
+use super::MyBusTrackerError;
+
+/// Value of the `Accept-Encoding` header sent with each request.
+pub const ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// Decompress a response body according to its `Content-Encoding` header.
+///
+/// Bodies without a `Content-Encoding`, or with an encoding other than gzip or deflate, are
+/// returned unchanged.
+pub fn decode(
+    content_encoding: Option<&HeaderValue>,
+    body: Vec<u8>,
+) -> Result<Vec<u8>, MyBusTrackerError> {
+    let encoding = content_encoding
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+
+    let mut decoded = Vec::with_capacity(body.len());
+    let result = match encoding.as_deref() {
+        Some("gzip") | Some("x-gzip") => GzDecoder::new(&body[..]).read_to_end(&mut decoded),
+        Some("deflate") => ZlibDecoder::new(&body[..]).read_to_end(&mut decoded),
+        _ => return Ok(body),
+    };
+
+    result.map_err(|e| MyBusTrackerError::CommunicationError {
+        cause: format!("Could not decompress response: {}", e),
+        status: None,
+        uri: None,
+        source: Some(Box::new(e)),
+    })?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    use super::*;
+
+    const BODY: &[u8] = br#"{"topoId": "20180101", "operatorId": "LB"}"#;
+
+    #[test]
+    fn gzip_and_deflate_bodies_are_decoded() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(BODY).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut deflate = ZlibEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(BODY).unwrap();
+        let deflate = deflate.finish().unwrap();
+
+        let encoding = |value| Some(HeaderValue::from_static(value));
+        assert_eq!(decode(encoding("gzip").as_ref(), gzip.clone()).unwrap(), BODY);
+        assert_eq!(decode(encoding(" X-GZIP ").as_ref(), gzip).unwrap(), BODY);
+        assert_eq!(decode(encoding("deflate").as_ref(), deflate).unwrap(), BODY);
+    }
+
+    #[test]
+    fn other_bodies_are_unchanged() {
+        assert_eq!(decode(None, BODY.to_vec()).unwrap(), BODY);
+        let identity = HeaderValue::from_static("identity");
+        assert_eq!(decode(Some(&identity), BODY.to_vec()).unwrap(), BODY);
+    }
+
+    #[test]
+    fn corrupt_bodies_fail() {
+        let gzip = HeaderValue::from_static("gzip");
+        let error = decode(Some(&gzip), b"oops!".to_vec()).unwrap_err();
+        assert!(matches!(error, MyBusTrackerError::CommunicationError { .. }), "{:?}", error);
+    }
+}
@@ -0,0 +1,60 @@
+//! CSV Export
+//!
+//! Writes bus stops as CSV, for offline analysis. Requires the `csv` feature.
+
+use std::io::{self, Write};
+
+use super::models::BusStops;
+
+impl BusStops {
+    /// Write the stops as CSV, with a header row.
+    ///
+    /// Columns are the stop ID, name, WGS84 latitude and longitude, orientation, and the
+    /// services calling at the stop, separated by `|`. Fields are quoted where necessary, e.g.
+    /// names containing commas.
+    pub fn to_csv<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["stop_id", "name", "lat", "lon", "orientation", "services"])?;
+        for bus_stop in &self.bus_stops {
+            let (lat, lon) = bus_stop.wgs84();
+            writer.write_record([
+                bus_stop.stop_id.as_str(),
+                &bus_stop.name,
+                &lat.to_string(),
+                &lon.to_string(),
+                &bus_stop.orientation.to_string(),
+                &bus_stop.services.join("|"),
+            ])?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUS_STOPS: &str = r#"{"busStops": [
+        {"operatorId": "LB", "stopId": "36232626", "name": "Princes Street, West End",
+         "x": 325500.5, "y": 673800.5, "cap": 90, "services": ["26", "X26"],
+         "dests": ["26A"]}
+    ]}"#;
+
+    #[test]
+    fn stops_are_written_with_a_header() {
+        let bus_stops: BusStops = serde_json::from_str(BUS_STOPS).unwrap();
+        let mut output = Vec::new();
+        bus_stops.to_csv(&mut output).unwrap();
+
+        let (lat, lon) = bus_stops.bus_stops[0].wgs84();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "stop_id,name,lat,lon,orientation,services".to_owned(),
+                format!("36232626,\"Princes Street, West End\",{},{},90,26|X26", lat, lon),
+            ]
+        );
+    }
+}
@@ -2,16 +2,19 @@
 //!
 //! For full documentation, see Section IV.3 of the My Bus Tracker API Guide (Version F)
 
-use hyper::{Method, Request};
-use super::{models, MyBusTracker, MyBusTrackerError};
-use futures::{self, Future};
+use super::{models, MyBusTracker, MyBusTrackerError, MAX_DAYS_AHEAD};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use hyper_util::client::legacy::connect::Connect;
+use std::collections::HashSet;
+use std::future::Future;
+#[allow(deprecated)]
 use chrono::{Date, Duration, Utc};
 
 /// Disruptions Web Service
 ///
 /// To use methods from the Disruptions Web Service, bring this trait into scope
 /// alongside your `MyBusTracker` instance.
-#[allow(stutter)]
+#[allow(clippy::module_name_repetitions)]
 pub trait DisruptionsServices {
     /// Get a list of ongoing disruptions.
     ///
@@ -21,34 +24,77 @@ pub trait DisruptionsServices {
         &self,
         disruption_type: &Option<&models::DisruptionType>,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Disruptions, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::Disruptions, MyBusTrackerError>> + Send;
+
+    /// Get a list of ongoing disruptions, of at least the given severity.
+    ///
+    /// As `get_disruptions`, but the web service cannot filter by level, so all disruptions
+    /// (of the requested type) are fetched, and those less severe than `level` discarded.
+    fn get_disruptions_at_least(
+        &self,
+        level: &models::DisruptionLevel,
+        disruption_type: &Option<&models::DisruptionType>,
+        operator: &models::Operator,
+    ) -> impl Future<Output = Result<models::Disruptions, MyBusTrackerError>> + Send;
+
+    /// Get a list of ongoing disruptions affecting the given service.
+    fn get_disruptions_for_service(
+        &self,
+        service_reference: &str,
+        operator: &models::Operator,
+    ) -> impl Future<Output = Result<models::Disruptions, MyBusTrackerError>> + Send;
+
+    /// Get a list of ongoing disruptions affecting the given bus stop.
+    fn get_disruptions_for_stop(
+        &self,
+        stop_id: &models::StopId,
+        operator: &models::Operator,
+    ) -> impl Future<Output = Result<models::Disruptions, MyBusTrackerError>> + Send;
 
     /// Get a list of ongoing diversions.
     ///
     /// You may request disruptions on:
     ///   - optionally, a specific service - the default is all services;
     ///   - optionally, a specific date, up to three-days in the future - the default is today;
+    #[allow(deprecated)]
     fn get_diversions(
         &self,
         service_reference: &Option<&str>,
         day: &Option<Date<Utc>>,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Diversions, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::Diversions, MyBusTrackerError>> + Send;
+
+    /// Get a list of ongoing diversions on any of several services, making up to `concurrency`
+    /// requests at once.
+    ///
+    /// As `get_diversions`, with one request per service. Diversions affecting several of the
+    /// services are only listed once. If any request fails, the first error is returned.
+    #[allow(deprecated)]
+    fn get_diversions_for_services(
+        &self,
+        service_references: &[&str],
+        day: &Option<Date<Utc>>,
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> impl Future<Output = Result<models::Diversions, MyBusTrackerError>> + Send;
 
     /// Get the description of a diversion for plotting on a map
     fn get_diversion_points(
         &self,
         diversion: &str,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::DiversionPoints, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::DiversionPoints, MyBusTrackerError>> + Send;
 }
 
-impl DisruptionsServices for MyBusTracker {
-    fn get_disruptions(
+impl<C> DisruptionsServices for MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    async fn get_disruptions(
         &self,
         disruption_type: &Option<&models::DisruptionType>,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Disruptions, Error = MyBusTrackerError>> {
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting disruptions";
@@ -60,25 +106,60 @@ impl DisruptionsServices for MyBusTracker {
 
         let uri_params = format!(
             "operatorId={}&type={}",
-            operator.to_string(),
+            operator,
             disruption_type
         );
-        let uri = match self.get_uri("getDisruptions", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri = self.get_uri("getDisruptions", Some(&uri_params))?;
 
-        let request = Request::new(Method::Get, uri);
+        self.make_request(uri).await
+    }
 
-        self.make_request(request)
+    async fn get_disruptions_at_least(
+        &self,
+        level: &models::DisruptionLevel,
+        disruption_type: &Option<&models::DisruptionType>,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        let mut disruptions = self.get_disruptions(disruption_type, operator).await?;
+        disruptions
+            .disruptions
+            .retain(|disruption| disruption.level >= *level);
+        Ok(disruptions)
     }
 
-    fn get_diversions(
+    async fn get_disruptions_for_service(
+        &self,
+        service_reference: &str,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        let disruption_type = models::DisruptionType::Service;
+        let mut disruptions = self.get_disruptions(&Some(&disruption_type), operator).await?;
+        disruptions
+            .disruptions
+            .retain(|disruption| disruption.affects(service_reference));
+        Ok(disruptions)
+    }
+
+    async fn get_disruptions_for_stop(
+        &self,
+        stop_id: &models::StopId,
+        operator: &models::Operator,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        let disruption_type = models::DisruptionType::BusStop;
+        let mut disruptions = self.get_disruptions(&Some(&disruption_type), operator).await?;
+        disruptions
+            .disruptions
+            .retain(|disruption| disruption.affects(stop_id.as_str()));
+        Ok(disruptions)
+    }
+
+    #[allow(deprecated)]
+    async fn get_diversions(
         &self,
         service_reference: &Option<&str>,
         day: &Option<Date<Utc>>,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Diversions, Error = MyBusTrackerError>> {
+    ) -> Result<models::Diversions, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting diversions";
@@ -93,8 +174,13 @@ impl DisruptionsServices for MyBusTracker {
             Some(day) => day.signed_duration_since(Utc::today()),
             None => Duration::days(0),
         };
-        if day_difference > Duration::days(3) || day_difference < Duration::days(0) {
-            return Box::new(futures::failed(MyBusTrackerError::DateOutOfBounds));
+        if day_difference > Duration::days(MAX_DAYS_AHEAD)
+            || day_difference < Duration::days(0)
+        {
+            return Err(MyBusTrackerError::DateOutOfBounds {
+                days_offset: day_difference.num_days(),
+                max: MAX_DAYS_AHEAD,
+            });
         }
 
         let uri_params = format!(
@@ -103,20 +189,52 @@ impl DisruptionsServices for MyBusTracker {
             service_reference,
             day_difference.num_days()
         );
-        let uri = match self.get_uri("getDiversions", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri = self.get_uri("getDiversions", Some(&uri_params))?;
+
+        self.make_request(uri).await
+    }
 
-        let request = Request::new(Method::Get, uri);
+    #[allow(deprecated)]
+    async fn get_diversions_for_services(
+        &self,
+        service_references: &[&str],
+        day: &Option<Date<Utc>>,
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> Result<models::Diversions, MyBusTrackerError> {
+        debug!(
+            self.logger,
+            "Getting diversions for several services";
+            "service_references" => ?service_references,
+            "day" => ?day,
+            "concurrency" => concurrency,
+            "operator" => ?operator,
+        );
+        let service_references: Vec<Option<&str>> =
+            service_references.iter().map(|reference| Some(*reference)).collect();
+        let requests: Vec<_> = service_references
+            .iter()
+            .map(|service_reference| self.get_diversions(service_reference, day, operator))
+            .collect();
+        let responses: Vec<models::Diversions> = stream::iter(requests)
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?;
 
-        self.make_request(request)
+        let mut seen = HashSet::new();
+        let diversions = responses
+            .into_iter()
+            .flat_map(|response| response.diversions)
+            .filter(|diversion| seen.insert(diversion.diversion_id.clone()))
+            .collect();
+        Ok(models::Diversions { diversions })
     }
-    fn get_diversion_points(
+
+    async fn get_diversion_points(
         &self,
         diversion: &str,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::DiversionPoints, Error = MyBusTrackerError>> {
+    ) -> Result<models::DiversionPoints, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting diversion points";
@@ -124,13 +242,221 @@ impl DisruptionsServices for MyBusTracker {
             "operator" => ?operator,
         );
         let uri_params = format!("operatorId={}&diversionId={}", operator, diversion);
-        let uri = match self.get_uri("getDiversionPoints", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri = self.get_uri("getDiversionPoints", Some(&uri_params))?;
+
+        let mut diversion_points: models::DiversionPoints = self.make_request(uri).await?;
+        diversion_points
+            .diversion_id
+            .get_or_insert_with(|| diversion.to_owned());
+        Ok(diversion_points)
+    }
+}
+
+/// Variants of the Disruptions Web Service methods, using the instance's default operator.
+///
+/// See `MyBusTracker::set_default_operator`.
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// See `DisruptionsServices::get_disruptions`.
+    pub async fn get_disruptions_default(
+        &self,
+        disruption_type: &Option<&models::DisruptionType>,
+    ) -> Result<models::Disruptions, MyBusTrackerError> {
+        self.get_disruptions(disruption_type, &self.default_operator)
+            .await
+    }
+
+    /// See `DisruptionsServices::get_diversions`.
+    #[allow(deprecated)]
+    pub async fn get_diversions_default(
+        &self,
+        service_reference: &Option<&str>,
+        day: &Option<Date<Utc>>,
+    ) -> Result<models::Diversions, MyBusTrackerError> {
+        self.get_diversions(service_reference, day, &self.default_operator)
+            .await
+    }
+
+    /// See `DisruptionsServices::get_diversion_points`.
+    pub async fn get_diversion_points_default(
+        &self,
+        diversion: &str,
+    ) -> Result<models::DiversionPoints, MyBusTrackerError> {
+        self.get_diversion_points(diversion, &self.default_operator)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use hyper::Uri;
+    use url::Url;
+
+    use super::*;
+    use crate::tests::{serve_json, serve_with};
+
+    /// One disruption of each level, to services and stops.
+    const DISRUPTIONS: &str = r#"{"disruptions": [
+        {"id": "d1", "operatorId": "LB", "level": 3, "type": 2, "targets": ["26", "X26"],
+         "validUntil": null, "message": "Diverted"},
+        {"id": "d2", "operatorId": "LB", "level": 1, "type": 3, "targets": ["36232626"],
+         "validUntil": null, "message": "Stop closed"},
+        {"id": "d3", "operatorId": "LB", "level": 2, "type": 2, "targets": ["1"],
+         "validUntil": null, "message": "Delays"}
+    ]}"#;
+
+    /// A tracker served the given body for every request, and the URIs of the requests it makes.
+    async fn tracker(body: &str) -> (MyBusTracker, Arc<Mutex<Vec<Uri>>>) {
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(serve_json(body).await)
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |uri| recorded.lock().unwrap().push(uri.clone()));
+        (tracker, requests)
+    }
+
+    fn ids(disruptions: &models::Disruptions) -> Vec<&str> {
+        disruptions
+            .disruptions
+            .iter()
+            .map(|disruption| disruption.id.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn disruptions_are_filtered_by_level() {
+        let (tracker, _) = tracker(DISRUPTIONS).await;
+        let operator = models::Operator::LothianBuses;
+
+        let minor = tracker
+            .get_disruptions_at_least(&models::DisruptionLevel::Minor, &None, &operator)
+            .await
+            .unwrap();
+        assert_eq!(ids(&minor), vec!["d1", "d3"]);
+
+        let major: Vec<&str> = minor
+            .at_least(models::DisruptionLevel::Major)
+            .iter()
+            .map(|disruption| disruption.id.as_str())
+            .collect();
+        assert_eq!(major, vec!["d1"]);
+    }
+
+    #[tokio::test]
+    async fn disruptions_are_filtered_by_target() {
+        let (tracker, requests) = tracker(DISRUPTIONS).await;
+        let operator = models::Operator::LothianBuses;
+
+        let service = tracker
+            .get_disruptions_for_service("X26", &operator)
+            .await
+            .unwrap();
+        assert_eq!(ids(&service), vec!["d1"]);
+
+        let stop = tracker
+            .get_disruptions_for_stop(&models::StopId::from("36232626"), &operator)
+            .await
+            .unwrap();
+        assert_eq!(ids(&stop), vec!["d2"]);
+
+        let unaffected = tracker
+            .get_disruptions_for_service("99", &operator)
+            .await
+            .unwrap();
+        assert!(unaffected.disruptions.is_empty());
+
+        let types: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|uri| uri.query().unwrap().rsplit('&').next().unwrap().to_owned())
+            .collect();
+        assert_eq!(types, vec!["type=2", "type=3", "type=2"]);
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn diversions_out_of_bounds_report_the_offset() {
+        let (tracker, requests) = tracker("").await;
+        let four_days = Utc::today() + Duration::days(4);
+
+        let error = tracker
+            .get_diversions(&None, &Some(four_days), &models::Operator::LothianBuses)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, MyBusTrackerError::DateOutOfBounds { days_offset: 4, max: 3 }),
+            "{:?}",
+            error
+        );
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    /// Serves `getDiversions` requests for service 26 with diversions 1 and 2, and for any
+    /// other service with diversions 2 and 3.
+    async fn serve_diversions() -> Url {
+        serve_with(|request| {
+            let service = request
+                .split(['?', '&', ' '])
+                .find_map(|pair| pair.strip_prefix("refService="))
+                .unwrap()
+                .to_owned();
+            let ids: &[&str] = if service == "26" { &["1", "2"] } else { &["2", "3"] };
+            let diversions: Vec<String> = ids
+                .iter()
+                .map(|id| {
+                    format!(
+                        r#"{{"ref": "v{id}", "diversionId": "{id}", "operatorId": "LB",
+                            "refService": "{service}", "startStopId": "1",
+                            "startStopName": "A", "startDate": "2018-01-01T00:00:00Z",
+                            "endStopId": "2", "endStopName": "B",
+                            "endDate": "2018-01-31T00:00:00Z", "days": "1111111",
+                            "length": 1200, "timeShift": 5, "cancelledBusStops": [],
+                            "temporaryBusStops": []}}"#,
+                        id = id,
+                        service = service
+                    )
+                })
+                .collect();
+            let body = format!(r#"{{"diversions": [{}]}}"#, diversions.join(","));
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .into_bytes()
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn diversions_of_several_services_are_merged() {
+        let tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(serve_diversions().await)
+            .build()
+            .unwrap();
 
-        let request = Request::new(Method::Get, uri);
+        let diversions = tracker
+            .get_diversions_for_services(&["26", "1"], &None, 2, &models::Operator::LothianBuses)
+            .await
+            .unwrap();
 
-        self.make_request(request)
+        let merged: Vec<(&str, &str)> = diversions
+            .diversions
+            .iter()
+            .map(|diversion| {
+                (diversion.diversion_id.as_str(), diversion.service_reference.as_str())
+            })
+            .collect();
+        assert_eq!(merged, vec![("1", "26"), ("2", "26"), ("3", "1")]);
     }
 }
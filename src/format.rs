@@ -0,0 +1,27 @@
+//! Response Formats
+//!
+//! The My Bus Tracker web service can respond in either JSON or XML.
+
+use std::fmt::{self, Display, Formatter};
+
+/// Format in which the My Bus Tracker web service is asked to respond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// JSON responses. This is the default.
+    #[default]
+    Json,
+    /// XML responses. Requires the `xml` feature.
+    #[cfg(feature = "xml")]
+    Xml,
+}
+
+impl Display for ResponseFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let printable = match *self {
+            ResponseFormat::Json => "json",
+            #[cfg(feature = "xml")]
+            ResponseFormat::Xml => "xml",
+        };
+        write!(f, "{}", printable)
+    }
+}
@@ -0,0 +1,255 @@
+//! Geographic coordinate conversions
+//!
+//! The My Bus Tracker web service locates stops and route points using Ordnance Survey National
+//! Grid (OSGB36) eastings and northings. These are converted to WGS84 latitude and longitude,
+//! as used by GPS and most web maps, following the Ordnance Survey's "A guide to coordinate
+//! systems in Great Britain".
+
+use std::f64::consts::PI;
+
+use serde_json::{json, Value};
+
+/// Airy 1830 ellipsoid semi-major axis, in metres.
+const AIRY_A: f64 = 6_377_563.396;
+/// Airy 1830 ellipsoid semi-minor axis, in metres.
+const AIRY_B: f64 = 6_356_256.909;
+/// WGS84 ellipsoid semi-major axis, in metres.
+const WGS84_A: f64 = 6_378_137.0;
+/// WGS84 ellipsoid semi-minor axis, in metres.
+const WGS84_B: f64 = 6_356_752.314_2;
+/// Mean radius of the Earth, in metres.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// National Grid scale factor on the central meridian.
+const F0: f64 = 0.999_601_271_7;
+/// Maximum number of iterations of the iterative steps of a conversion. Each converges well
+/// within this for any real grid reference, and it stops non-finite input looping forever.
+const MAX_ITERATIONS: usize = 10;
+
+/// National Grid true origin latitude, in degrees.
+const LAT0: f64 = 49.0;
+/// National Grid true origin longitude, in degrees.
+const LON0: f64 = -2.0;
+/// National Grid northing of the true origin, in metres.
+const N0: f64 = -100_000.0;
+/// National Grid easting of the true origin, in metres.
+const E0: f64 = 400_000.0;
+
+/// Helmert transformation parameters from OSGB36 to WGS84: translations in metres, scale in
+/// parts per million, and rotations in arc-seconds.
+const TX: f64 = 446.448;
+const TY: f64 = -125.157;
+const TZ: f64 = 542.060;
+const S: f64 = -20.4894;
+const RX: f64 = 0.1502;
+const RY: f64 = 0.2470;
+const RZ: f64 = 0.8421;
+
+/// Convert an OSGB36 National Grid easting and northing to a WGS84 `(latitude, longitude)`, in
+/// degrees.
+///
+/// The conversion is accurate to within a few metres, which is the accuracy of the Helmert
+/// transformation between the two datums.
+pub fn osgb36_to_wgs84(easting: f64, northing: f64) -> (f64, f64) {
+    let (lat, lon) = grid_to_osgb36(easting, northing);
+    let (x, y, z) = to_cartesian(lat, lon, AIRY_A, AIRY_B);
+    let (x, y, z) = helmert(x, y, z);
+    let (lat, lon) = from_cartesian(x, y, z, WGS84_A, WGS84_B);
+    (lat.to_degrees(), lon.to_degrees())
+}
+
+/// The great-circle distance, in metres, between two WGS84 `(latitude, longitude)` points given
+/// in degrees.
+///
+/// Uses the haversine formula on a spherical Earth, which is accurate to within around 0.5%.
+pub fn haversine(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat_a, lon_a) = (a.0.to_radians(), a.1.to_radians());
+    let (lat_b, lon_b) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((lat_b - lat_a) / 2.0).sin().powi(2)
+        + lat_a.cos() * lat_b.cos() * ((lon_b - lon_a) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().min(1.0).asin()
+}
+
+/// Order WGS84 `(latitude, longitude)` points by their `order` value, keeping only the first
+/// point of each order.
+pub fn polyline<I>(points: I) -> Vec<(f64, f64)>
+where
+    I: Iterator<Item = (u32, (f64, f64))>,
+{
+    let mut points: Vec<(u32, (f64, f64))> = points.collect();
+    points.sort_by_key(|&(order, _)| order);
+    points.dedup_by_key(|&mut (order, _)| order);
+    points.into_iter().map(|(_, point)| point).collect()
+}
+
+/// A GeoJSON `Feature`, with a `LineString` geometry through the given WGS84
+/// `(latitude, longitude)` points.
+pub fn line_string_feature(points: &[(f64, f64)], properties: Value) -> Value {
+    let coordinates: Vec<[f64; 2]> = points.iter().map(|&(lat, lon)| [lon, lat]).collect();
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+        "properties": properties,
+    })
+}
+
+/// A GeoJSON `Feature`, with a `Point` geometry at the given WGS84 `(latitude, longitude)`.
+pub fn point_feature(point: (f64, f64), properties: Value) -> Value {
+    let (lat, lon) = point;
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [lon, lat],
+        },
+        "properties": properties,
+    })
+}
+
+/// Inverse transverse Mercator projection of a grid reference onto the Airy 1830 ellipsoid,
+/// returning the OSGB36 latitude and longitude in radians.
+#[allow(clippy::many_single_char_names)]
+fn grid_to_osgb36(easting: f64, northing: f64) -> (f64, f64) {
+    let (a, b) = (AIRY_A, AIRY_B);
+    let lat0 = LAT0.to_radians();
+    let lon0 = LON0.to_radians();
+    let e2 = 1.0 - (b * b) / (a * a);
+    let n = (a - b) / (a + b);
+
+    let meridional_arc = |lat: f64| {
+        let (n2, n3) = (n * n, n * n * n);
+        b * F0
+            * ((1.0 + n + 5.0 / 4.0 * n2 + 5.0 / 4.0 * n3) * (lat - lat0)
+                - (3.0 * n + 3.0 * n2 + 21.0 / 8.0 * n3) * (lat - lat0).sin() * (lat + lat0).cos()
+                + (15.0 / 8.0 * n2 + 15.0 / 8.0 * n3)
+                    * (2.0 * (lat - lat0)).sin()
+                    * (2.0 * (lat + lat0)).cos()
+                - 35.0 / 24.0 * n3 * (3.0 * (lat - lat0)).sin() * (3.0 * (lat + lat0)).cos())
+    };
+
+    let mut lat = lat0;
+    let mut m = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        lat += (northing - N0 - m) / (a * F0);
+        m = meridional_arc(lat);
+        if (northing - N0 - m).abs() < 0.000_01 {
+            break;
+        }
+    }
+
+    let (sin_lat, cos_lat, tan_lat) = (lat.sin(), lat.cos(), lat.tan());
+    let nu = a * F0 / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    let rho = a * F0 * (1.0 - e2) / (1.0 - e2 * sin_lat * sin_lat).powf(1.5);
+    let eta2 = nu / rho - 1.0;
+    let (tan2, tan4, tan6) = (tan_lat.powi(2), tan_lat.powi(4), tan_lat.powi(6));
+    let sec_lat = 1.0 / cos_lat;
+
+    let vii = tan_lat / (2.0 * rho * nu);
+    let viii = tan_lat / (24.0 * rho * nu.powi(3)) * (5.0 + 3.0 * tan2 + eta2 - 9.0 * tan2 * eta2);
+    let ix = tan_lat / (720.0 * rho * nu.powi(5)) * (61.0 + 90.0 * tan2 + 45.0 * tan4);
+    let x = sec_lat / nu;
+    let xi = sec_lat / (6.0 * nu.powi(3)) * (nu / rho + 2.0 * tan2);
+    let xii = sec_lat / (120.0 * nu.powi(5)) * (5.0 + 28.0 * tan2 + 24.0 * tan4);
+    let xiia =
+        sec_lat / (5040.0 * nu.powi(7)) * (61.0 + 662.0 * tan2 + 1320.0 * tan4 + 720.0 * tan6);
+
+    let de = easting - E0;
+    let lat = lat - vii * de.powi(2) + viii * de.powi(4) - ix * de.powi(6);
+    let lon = lon0 + x * de - xi * de.powi(3) + xii * de.powi(5) - xiia * de.powi(7);
+    (lat, lon)
+}
+
+/// Convert a latitude and longitude (in radians, at zero height) on the given ellipsoid to
+/// cartesian coordinates.
+fn to_cartesian(lat: f64, lon: f64, a: f64, b: f64) -> (f64, f64, f64) {
+    let e2 = 1.0 - (b * b) / (a * a);
+    let nu = a / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    (
+        nu * lat.cos() * lon.cos(),
+        nu * lat.cos() * lon.sin(),
+        (1.0 - e2) * nu * lat.sin(),
+    )
+}
+
+/// Apply the OSGB36 to WGS84 Helmert transformation to cartesian coordinates.
+fn helmert(x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    let arc_seconds = PI / (180.0 * 3600.0);
+    let (rx, ry, rz) = (RX * arc_seconds, RY * arc_seconds, RZ * arc_seconds);
+    let s = 1.0 + S / 1_000_000.0;
+    (
+        TX + s * x - rz * y + ry * z,
+        TY + rz * x + s * y - rx * z,
+        TZ - ry * x + rx * y + s * z,
+    )
+}
+
+/// Convert cartesian coordinates to a latitude and longitude, in radians, on the given
+/// ellipsoid.
+fn from_cartesian(x: f64, y: f64, z: f64, a: f64, b: f64) -> (f64, f64) {
+    let e2 = 1.0 - (b * b) / (a * a);
+    let p = (x * x + y * y).sqrt();
+
+    let mut lat = z.atan2(p * (1.0 - e2));
+    for _ in 0..MAX_ITERATIONS {
+        let nu = a / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        let next = (z + e2 * nu * lat.sin()).atan2(p);
+        let converged = (next - lat).abs() < 1e-12;
+        lat = next;
+        if converged {
+            break;
+        }
+    }
+    (lat, y.atan2(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_reference_is_projected_as_in_the_os_worked_example() {
+        // The worked example of the Ordnance Survey's guide: 52°39'27.2531"N, 1°43'4.5177"E.
+        let (lat, lon) = grid_to_osgb36(651_409.903, 313_177.270);
+        assert!((lat.to_degrees() - (52.0 + 39.0 / 60.0 + 27.2531 / 3600.0)).abs() < 1e-7);
+        assert!((lon.to_degrees() - (1.0 + 43.0 / 60.0 + 4.5177 / 3600.0)).abs() < 1e-7);
+    }
+
+    #[test]
+    fn edinburgh_stop_is_converted_to_wgs84() {
+        // Princes Street, by the Scott Monument (NT 25600 73900).
+        let (lat, lon) = osgb36_to_wgs84(325_600.0, 673_900.0);
+        assert!((lat - 55.9523).abs() < 1e-4, "{}", lat);
+        assert!((lon - -3.1930).abs() < 1e-4, "{}", lon);
+    }
+
+    #[test]
+    fn non_finite_grid_references_do_not_hang() {
+        let (lat, lon) = osgb36_to_wgs84(f64::NAN, 673_900.0);
+        assert!(lat.is_nan() && lon.is_nan());
+        let (lat, _) = osgb36_to_wgs84(325_600.0, f64::INFINITY);
+        assert!(!lat.is_finite());
+    }
+
+    #[test]
+    fn haversine_distance_is_zero_for_the_same_point_and_symmetric() {
+        let princes_street = (55.9523, -3.1930);
+        let leith_walk = (55.9645, -3.1756);
+        assert_eq!(haversine(princes_street, princes_street), 0.0);
+        assert_eq!(
+            haversine(princes_street, leith_walk),
+            haversine(leith_walk, princes_street)
+        );
+    }
+
+    #[test]
+    fn haversine_distance_along_a_meridian() {
+        // A hundredth of a degree of latitude is a hundredth of the degree's arc of the Earth.
+        let expected = EARTH_RADIUS * PI / 180.0 / 100.0;
+        let distance = haversine((55.95, -3.19), (55.96, -3.19));
+        assert!((distance - expected).abs() < 0.01, "{}", distance);
+        assert!((distance - 1112.0).abs() < 1.0, "{}", distance);
+    }
+}
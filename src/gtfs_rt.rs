@@ -0,0 +1,140 @@
+//! GTFS-realtime Export
+//!
+//! Converts journey times into GTFS-realtime `TripUpdate` messages, for consumption by transit
+//! data pipelines. Requires the `gtfs-rt` feature.
+
+use chrono::{DateTime, Duration, Utc};
+use gtfs_realtime::trip_update::stop_time_update::ScheduleRelationship;
+use gtfs_realtime::trip_update::{StopTimeEvent, StopTimeUpdate};
+use gtfs_realtime::{TripDescriptor, TripUpdate, VehicleDescriptor};
+
+use super::models::{JourneyTime, JourneyTimeData, JourneyTimes};
+
+impl JourneyTimes {
+    /// Convert each journey into a GTFS-realtime `TripUpdate`.
+    ///
+    /// See `JourneyTime::to_trip_update`.
+    pub fn to_trip_updates(&self, reference: DateTime<Utc>) -> Vec<TripUpdate> {
+        self.journey_times
+            .iter()
+            .map(|journey_time| journey_time.to_trip_update(reference))
+            .collect()
+    }
+}
+
+impl JourneyTime {
+    /// Convert the journey into a GTFS-realtime `TripUpdate`, given the time at which the
+    /// response was fetched.
+    ///
+    /// The trip is identified by the journey ID and service reference, and the vehicle by the
+    /// bus ID, if known. Each stop becomes a `StopTimeUpdate`, ordered by its `order`, with an
+    /// estimated arrival time counted from `reference`.
+    ///
+    /// Every stop is marked as scheduled. A stop flagged with a disruption is still served, so
+    /// the flag is not mapped; the disruption itself is available from `get_disruptions`.
+    pub fn to_trip_update(&self, reference: DateTime<Utc>) -> TripUpdate {
+        let mut journey_times: Vec<&JourneyTimeData> = self.journey_times.iter().collect();
+        journey_times.sort_by_key(|time| time.order);
+
+        TripUpdate {
+            trip: TripDescriptor {
+                trip_id: Some(self.journey_id.clone()),
+                route_id: Some(self.service_reference.clone()),
+                ..TripDescriptor::default()
+            },
+            vehicle: self.bus_id.as_ref().map(|bus_id| VehicleDescriptor {
+                id: Some(bus_id.clone()),
+                ..VehicleDescriptor::default()
+            }),
+            stop_time_update: journey_times
+                .into_iter()
+                .map(|time| stop_time_update(time, reference))
+                .collect(),
+            timestamp: u64::try_from(reference.timestamp()).ok(),
+            ..TripUpdate::default()
+        }
+    }
+}
+
+/// Convert the arrival at a single stop into a `StopTimeUpdate`.
+fn stop_time_update(time: &JourneyTimeData, reference: DateTime<Utc>) -> StopTimeUpdate {
+    let arrival = reference + Duration::minutes(i64::from(time.minutes));
+    StopTimeUpdate {
+        stop_sequence: Some(time.order),
+        stop_id: Some(time.stop_id.to_string()),
+        arrival: Some(StopTimeEvent {
+            time: Some(arrival.timestamp()),
+            ..StopTimeEvent::default()
+        }),
+        schedule_relationship: Some(ScheduleRelationship::Scheduled as i32),
+        ..StopTimeUpdate::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const JOURNEY_TIMES: &str = r#"{"journeyTimes": [{
+        "journeyId": "1234", "busId": "567", "operatorId": "LB",
+        "refService": "26", "mnemoService": "26", "nameService": "Clerwood - Seton Sands",
+        "refDest": "26A", "nameDest": "Seton Sands",
+        "journeyTimeDatas": [
+            {"order": 2, "stopId": "36232627", "stopName": "York Place", "day": 0,
+             "time": "14:30", "minutes": 3, "reliability": "H", "type": "N",
+             "busStopDisruption": true},
+            {"order": 1, "stopId": "36232626", "stopName": "Princes Street", "day": 0,
+             "time": "14:25", "minutes": -2, "reliability": "H", "type": "R",
+             "busStopDisruption": false}
+        ],
+        "globalDisruption": false, "serviceDisruption": false,
+        "serviceDiversion": false
+    }]}"#;
+
+    #[test]
+    fn journeys_become_trip_updates() {
+        let journey_times: JourneyTimes = serde_json::from_str(JOURNEY_TIMES).unwrap();
+        let reference = Utc.with_ymd_and_hms(2018, 1, 2, 14, 27, 0).unwrap();
+        let trip_updates = journey_times.to_trip_updates(reference);
+        assert_eq!(trip_updates.len(), 1);
+
+        let trip_update = &trip_updates[0];
+        assert_eq!(trip_update.trip.trip_id.as_deref(), Some("1234"));
+        assert_eq!(trip_update.trip.route_id.as_deref(), Some("26"));
+        assert_eq!(trip_update.vehicle.as_ref().unwrap().id.as_deref(), Some("567"));
+        assert_eq!(trip_update.timestamp, Some(reference.timestamp() as u64));
+
+        let stops: Vec<(Option<u32>, Option<&str>, Option<i64>)> = trip_update
+            .stop_time_update
+            .iter()
+            .map(|update| {
+                (
+                    update.stop_sequence,
+                    update.stop_id.as_deref(),
+                    update.arrival.as_ref().and_then(|arrival| arrival.time),
+                )
+            })
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                (Some(1), Some("36232626"), Some(reference.timestamp() - 120)),
+                (Some(2), Some("36232627"), Some(reference.timestamp() + 180)),
+            ]
+        );
+    }
+
+    #[test]
+    fn disrupted_stops_remain_scheduled() {
+        let journey_times: JourneyTimes = serde_json::from_str(JOURNEY_TIMES).unwrap();
+        let trip_update = journey_times.journey_times[0].to_trip_update(Utc::now());
+
+        assert!(trip_update
+            .stop_time_update
+            .iter()
+            .all(|update| update.schedule_relationship
+                == Some(ScheduleRelationship::Scheduled as i32)));
+    }
+}
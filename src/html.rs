@@ -0,0 +1,124 @@
+//! HTML to plain text
+//!
+//! Disruption messages are marked up with HTML, for display on the My Bus Tracker website. This
+//! is a deliberately lightweight conversion to readable plain text, rather than a full HTML
+//! parser: tags are removed, line breaks and the ends of block elements become newlines, and
+//! character references are decoded.
+
+/// Tags, by lowercase name, which end a line of text.
+const LINE_BREAK_TAGS: [&str; 4] = ["br", "/p", "/div", "/li"];
+
+/// Convert a fragment of HTML to plain text.
+///
+/// Runs of whitespace within each line are collapsed to a single space, as a browser would, and
+/// leading and trailing blank lines removed.
+pub fn to_plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' && starts_tag(&rest[1..]) {
+            if let Some(end) = rest.find('>') {
+                if is_line_break(&rest[1..end]) {
+                    text.push('\n');
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        if c == '&' {
+            if let Some((decoded, length)) = decode_reference(rest) {
+                text.push(decoded);
+                rest = &rest[length..];
+                continue;
+            }
+        }
+        text.push(if c == '\n' || c == '\r' { ' ' } else { c });
+        rest = &rest[c.len_utf8()..];
+    }
+
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    let first = lines.iter().position(|line| !line.is_empty());
+    let last = lines.iter().rposition(|line| !line.is_empty());
+    match (first, last) {
+        (Some(first), Some(last)) => lines[first..=last].join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Whether the text following a `<` is the start of a tag, comment or closing tag, rather than
+/// a literal less-than sign.
+fn starts_tag(after: &str) -> bool {
+    after
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!')
+}
+
+/// Whether the tag with the given contents (between `<` and `>`) ends a line.
+fn is_line_break(tag: &str) -> bool {
+    let name = tag
+        .split(|c: char| c.is_whitespace() || c == '>')
+        .next()
+        .unwrap_or("")
+        .trim_end_matches('/')
+        .to_ascii_lowercase();
+    LINE_BREAK_TAGS.contains(&name.as_str())
+}
+
+/// Decode the character reference at the start of the given text, returning the character and
+/// the length of the reference. Unknown or malformed references are not decoded.
+fn decode_reference(text: &str) -> Option<(char, usize)> {
+    let end = text.char_indices().take(12).find(|&(_, c)| c == ';')?.0;
+    let name = &text[1..end];
+    let decoded = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        _ => {
+            let code = if let Some(hex) = name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok()?
+            } else {
+                name.strip_prefix('#')?.parse().ok()?
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((decoded, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_is_stripped_and_line_breaks_kept() {
+        let html = "<p>Service 26 is <b>diverted</b> via\n  Leith Walk.</p>\
+                    <p>See <a href=\"/diversions?id=1&amp;op=LB\">details</a><br/>Sorry!</p>";
+        assert_eq!(
+            to_plain_text(html),
+            "Service 26 is diverted via Leith Walk.\nSee details\nSorry!"
+        );
+    }
+
+    #[test]
+    fn character_references_are_decoded() {
+        assert_eq!(
+            to_plain_text("Fish &amp; chips &lt;3 &#163;5 &#x20AC;6"),
+            "Fish & chips <3 £5 €6"
+        );
+        assert_eq!(to_plain_text("AT&T &bogus; &#xZZ;"), "AT&T &bogus; &#xZZ;");
+    }
+
+    #[test]
+    fn literal_less_than_signs_are_kept() {
+        assert_eq!(to_plain_text("Delays < 5 minutes<BR>"), "Delays < 5 minutes");
+        assert_eq!(to_plain_text("<br><br>  "), "");
+    }
+}
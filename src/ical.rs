@@ -0,0 +1,188 @@
+//! iCalendar Export
+//!
+//! Renders departures as iCalendar (RFC 5545) events, e.g. to add regular journeys to a
+//! calendar. Requires the `ical` feature.
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Europe::London;
+
+use super::models::{BusTime, BusTimes, TimeData};
+
+/// Length of each departure event, in minutes.
+const EVENT_MINUTES: u32 = 5;
+/// Maximum length of a content line, in octets, before it must be folded.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Definition of the Europe/London time zone, in which event times are given.
+const LONDON_VTIMEZONE: &str = "BEGIN:VTIMEZONE\r\n\
+TZID:Europe/London\r\n\
+BEGIN:DAYLIGHT\r\n\
+TZOFFSETFROM:+0000\r\n\
+TZOFFSETTO:+0100\r\n\
+TZNAME:BST\r\n\
+DTSTART:19700329T010000\r\n\
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\n\
+END:DAYLIGHT\r\n\
+BEGIN:STANDARD\r\n\
+TZOFFSETFROM:+0100\r\n\
+TZOFFSETTO:+0000\r\n\
+TZNAME:GMT\r\n\
+DTSTART:19701025T020000\r\n\
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r\n\
+END:STANDARD\r\n\
+END:VTIMEZONE\r\n";
+
+impl BusTimes {
+    /// Render all departures as an iCalendar document, with one event per departure.
+    ///
+    /// See `to_ical`.
+    pub fn to_ical(&self, reference: DateTime<Utc>) -> String {
+        to_ical(self.all_times(), reference)
+    }
+}
+
+/// Render the given departures as an iCalendar document, with one event per departure.
+///
+/// Each event starts at the estimated departure time, relative to the time the response was
+/// fetched (`reference`), and is given in Europe/London local time. Its summary names the
+/// service and destination, and its location the stop. Departures may be filtered first, e.g.
+/// with `BusTimes::all_times`.
+pub fn to_ical<'a, I>(departures: I, reference: DateTime<Utc>) -> String
+where
+    I: IntoIterator<Item = (&'a BusTime, &'a TimeData)>,
+{
+    let mut calendar = String::new();
+    calendar.push_str("BEGIN:VCALENDAR\r\n");
+    calendar.push_str("VERSION:2.0\r\n");
+    push_line(
+        &mut calendar,
+        &format!("PRODID:-//{}//EN", super::default_user_agent()),
+    );
+    calendar.push_str(LONDON_VTIMEZONE);
+
+    for (bus_time, time) in departures {
+        let start = time.arrival_at(reference).with_timezone(&London);
+        let destination = bus_time
+            .destination_name
+            .as_ref()
+            .unwrap_or(&time.terminus);
+
+        calendar.push_str("BEGIN:VEVENT\r\n");
+        push_line(
+            &mut calendar,
+            &format!(
+                "UID:{}-{}@my-bus-tracker",
+                escape(&time.journey_id),
+                escape(bus_time.stop_id.as_str())
+            ),
+        );
+        push_line(
+            &mut calendar,
+            &format!("DTSTAMP:{}", reference.format("%Y%m%dT%H%M%SZ")),
+        );
+        push_line(
+            &mut calendar,
+            &format!("DTSTART;TZID=Europe/London:{}", start.format("%Y%m%dT%H%M%S")),
+        );
+        push_line(&mut calendar, &format!("DURATION:PT{}M", EVENT_MINUTES));
+        push_line(
+            &mut calendar,
+            &format!(
+                "SUMMARY:{}",
+                escape(&format!("{} to {}", bus_time.service_mnemonic, destination))
+            ),
+        );
+        push_line(
+            &mut calendar,
+            &format!("LOCATION:{}", escape(&bus_time.stop_name)),
+        );
+        calendar.push_str("END:VEVENT\r\n");
+    }
+
+    calendar.push_str("END:VCALENDAR\r\n");
+    calendar
+}
+
+/// Escape text for use in an iCalendar property value.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append a content line, folding it onto continuation lines if it is too long.
+fn push_line(calendar: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            calendar.push_str("\r\n ");
+            length = 1;
+        }
+        calendar.push(c);
+        length += c.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const BUS_TIMES: &str = r#"{"busTimes": [{
+        "operatorId": "LB", "stopId": "36232626", "stopName": "Princes Street, West End",
+        "refService": "26", "mnemoService": "26", "nameService": "Clerwood - Seton Sands",
+        "refDest": "26A", "nameDest": "Seton Sands",
+        "timeDatas": [
+            {"day": 0, "time": "14:35", "minutes": 3, "reliability": "H", "type": "N",
+             "terminus": "Seton Sands", "journeyId": "1234", "busId": "567"}
+        ],
+        "globalDisruption": false, "serviceDisruption": false,
+        "busStopDisruption": false, "serviceDiversion": false
+    }]}"#;
+
+    #[test]
+    fn departures_are_rendered_as_london_events() {
+        let bus_times: BusTimes = serde_json::from_str(BUS_TIMES).unwrap();
+        let reference = Utc.with_ymd_and_hms(2018, 7, 1, 13, 32, 0).unwrap();
+        let calendar = bus_times.to_ical(reference);
+        let lines: Vec<&str> = calendar.split("\r\n").collect();
+
+        assert_eq!(lines[0], "BEGIN:VCALENDAR");
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains("TZID:Europe/London\r\n"));
+
+        let event = lines.iter().position(|&line| line == "BEGIN:VEVENT").unwrap();
+        assert_eq!(
+            &lines[event..event + 8],
+            &[
+                "BEGIN:VEVENT",
+                "UID:1234-36232626@my-bus-tracker",
+                "DTSTAMP:20180701T133200Z",
+                "DTSTART;TZID=Europe/London:20180701T143500",
+                "DURATION:PT5M",
+                "SUMMARY:26 to Seton Sands",
+                "LOCATION:Princes Street\\, West End",
+                "END:VEVENT",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let mut calendar = String::new();
+        push_line(&mut calendar, &"x".repeat(100));
+        assert_eq!(calendar, format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(25)));
+    }
+}
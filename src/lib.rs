@@ -8,82 +8,350 @@
 //! visit <http://www.mybustracker.co.uk/?page=API%20Key>
 
 extern crate chrono;
-#[macro_use]
-extern crate failure;
-extern crate futures;
+#[cfg(feature = "chrono-tz")]
+extern crate chrono_tz;
+#[cfg(feature = "csv")]
+extern crate csv;
+#[cfg(feature = "compression")]
+extern crate flate2;
+extern crate futures_util;
+#[cfg(feature = "gtfs-rt")]
+extern crate gtfs_realtime;
+extern crate http_body_util;
 extern crate hyper;
+#[cfg(feature = "tls")]
+extern crate hyper_rustls;
+extern crate hyper_util;
+#[cfg(feature = "log")]
+extern crate log;
 extern crate md5;
 extern crate serde;
-#[macro_use]
-extern crate serde_derive;
 extern crate serde_json;
+#[cfg(feature = "xml")]
+extern crate serde_xml_rs;
 #[macro_use]
 extern crate slog;
-extern crate tokio_core;
+extern crate thiserror;
+extern crate tokio;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 extern crate url;
 
-// Tokio/Future Imports
-use futures::{Future, Stream};
-use futures::future::ok;
-use tokio_core::reactor::Handle;
-
 // Hyper Imports
-use hyper::Uri;
-use hyper::client::{Client, HttpConnector, Request};
-use hyper::header::UserAgent;
-// TODO: TLS Support - if MyBusTracker WS supports it
-//#[cfg(feature = "rustls")]
-//use hyper_rustls::HttpsConnector;
-//#[cfg(feature = "rust-native-tls")]
-//use hyper_tls;
-//#[cfg(feature = "rust-native-tls")]
-//type HttpsConnector = hyper_tls::HttpsConnector<hyper::client::HttpConnector>;
-
-use std::rc::Rc;
-use std::cell::RefCell;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{CONTENT_LENGTH, USER_AGENT};
+#[cfg(feature = "compression")]
+use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use hyper::http::uri::InvalidUri;
+use hyper::{Request, Response, Uri};
+#[cfg(feature = "tls")]
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use chrono::prelude::*;
-use failure::Error;
+use futures_util::future::{BoxFuture, FutureExt};
+use thiserror::Error as ThisError;
 use slog::Logger;
 use url::Url;
 
 pub mod models;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "gtfs-rt")]
+pub mod gtfs_rt;
+#[cfg(feature = "ical")]
+pub mod ical;
+mod builder;
+mod cache;
+mod coalesce;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "csv")]
+mod csv_export;
+mod clock;
+mod format;
+mod geo;
+mod html;
+#[cfg(feature = "chrono-tz")]
+mod local_time;
+#[cfg(feature = "log")]
+mod log_facade;
 mod disruptions;
 mod topological;
 mod bustimes;
+mod overview;
+mod refresh;
+mod retry;
+#[cfg(feature = "tracing")]
+mod tracing_span;
+mod transport;
+mod watch;
 
+pub use builder::MyBusTrackerBuilder;
+pub use cache::CachedMyBusTracker;
+pub use clock::{Clock, SystemClock};
+pub use format::ResponseFormat;
 pub use disruptions::DisruptionsServices;
 pub use topological::TopologicalServices;
 pub use bustimes::BusTimesService;
-
-use hyper::error::UriError;
+pub use retry::RetryPolicy;
+pub use transport::{MockTransport, Transport, TransportFuture};
 
 const APP_NAME: Option<&'static str> = option_env!("CARGO_PKG_NAME");
 const APP_VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+const DEFAULT_ROOT_URL: &str = "http://ws.mybustracker.co.uk/";
+/// Maximum number of bytes of a response body to include in error messages.
+const ERROR_BODY_SNIPPET_LENGTH: usize = 256;
+/// Maximum number of bytes to allocate for a response body before it is received.
+const MAX_BODY_PREALLOCATION: usize = 16 * 1024 * 1024;
+
+/// Maximum number of timetables which may be requested at once from `get_bus_times`.
+pub const MAX_TIMETABLES: usize = 5;
+/// Maximum number of departures which may be requested per timetable or stop.
+pub const MAX_DEPARTURES: u8 = 10;
+/// Maximum number of days ahead of today for which times or diversions may be requested.
+pub const MAX_DAYS_AHEAD: i64 = 3;
+
+/// The HTTPS connector with which HTTP API requests are made, as the `tls` feature is enabled.
+///
+/// This supports both `http://` and `https://` base URLs. The default base URL is still
+/// `http://`, so an `https://` base URL must be set explicitly.
+#[cfg(feature = "tls")]
+pub type Connector = HttpsConnector<HttpConnector>;
+/// The plain HTTP connector with which HTTP API requests are made, as the `tls` feature is
+/// disabled.
+///
+/// Only `http://` base URLs are supported. Enable the `tls` feature to use an `https://` base
+/// URL.
+#[cfg(not(feature = "tls"))]
+pub type Connector = HttpConnector;
+
+/// An underlying error, as the source of a `MyBusTrackerError`.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 /// Errors that can be raised by `MyBusTracker`
-#[derive(Debug, Fail)]
+///
+/// Where a variant wraps an underlying error (e.g. from hyper or serde), it is kept as the
+/// `source` of the variant, and can be downcast to its concrete type.
+///
+/// Communication and deserialization errors from a web service request carry the URI of the
+/// request, with its `key` parameter redacted, so that they can be logged safely.
+#[derive(Debug, ThisError)]
 pub enum MyBusTrackerError {
-    #[fail(display = "Internal error")]
-    InternalError { cause: String },
-    #[fail(display = "Error communicating with MyBusTracker")]
-    CommunicationError { cause: String },
-    #[fail(display = "Date out of bounds")]
-    DateOutOfBounds,
-    #[fail(display = "Too many timetables requested")]
-    TooManyTimetables,
-    #[fail(display = "Too many departures requested")]
-    TooManyDepartures,
+    #[error("Internal error")]
+    InternalError {
+        cause: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    #[error("Error communicating with MyBusTracker: {cause}")]
+    CommunicationError {
+        cause: String,
+        status: Option<u16>,
+        /// The URI of the failed request, with the API key redacted, if known.
+        uri: Option<String>,
+        #[source]
+        source: Option<BoxError>,
+    },
+    #[error("Error deserializing response: {cause}")]
+    Deserialization {
+        cause: String,
+        line: usize,
+        column: usize,
+        snippet: String,
+        /// The URI of the request, with the API key redacted, if known.
+        uri: Option<String>,
+        #[source]
+        source: BoxError,
+    },
+    #[error("Date out of bounds: requested {days_offset} days ahead (max {max})")]
+    DateOutOfBounds { days_offset: i64, max: i64 },
+    #[error("{requested} timetables requested (max {max})")]
+    TooManyTimetables { requested: usize, max: usize },
+    #[error("No timetables requested")]
+    NoTimetablesRequested,
+    #[error("Empty response")]
+    EmptyResponse,
+    #[error("{requested} departures requested (max {max})")]
+    TooManyDepartures { requested: u8, max: u8 },
+    #[error("A stop ID is required when requesting times by journey ID")]
+    MissingStopId,
+    #[error("Request timed out")]
+    Timeout,
+    #[error("API key is empty")]
+    EmptyApiKey,
+    #[error("Missing required configuration: {field}")]
+    MissingConfiguration { field: &'static str },
+    #[error("Invalid timetable: {field} is empty")]
+    InvalidTimetable { field: &'static str },
+    #[error("Unknown stop: {stop_id}")]
+    UnknownStop { stop_id: models::StopId },
+}
+
+impl MyBusTrackerError {
+    /// Whether the error may be transient, such that retrying the request could succeed.
+    ///
+    /// Only communication errors are retryable - either failures to complete the HTTP request at
+    /// all, or server error (5xx) responses.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            MyBusTrackerError::CommunicationError { status: None, .. } => true,
+            MyBusTrackerError::CommunicationError {
+                status: Some(status),
+                ..
+            } => status >= 500,
+            _ => false,
+        }
+    }
+
+    /// The URI of the request which failed, with the API key redacted, if known.
+    pub fn uri(&self) -> Option<&str> {
+        match *self {
+            MyBusTrackerError::CommunicationError { ref uri, .. }
+            | MyBusTrackerError::Deserialization { ref uri, .. } => uri.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Attach the URI of the request which failed, with the API key redacted, to
+    /// communication and deserialization errors which don't already carry one.
+    fn with_uri(mut self, request_uri: &Uri) -> Self {
+        match self {
+            MyBusTrackerError::CommunicationError { ref mut uri, .. }
+            | MyBusTrackerError::Deserialization { ref mut uri, .. } => {
+                uri.get_or_insert_with(|| redact_uri(request_uri));
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+impl From<hyper::Error> for MyBusTrackerError {
+    fn from(e: hyper::Error) -> Self {
+        MyBusTrackerError::CommunicationError {
+            cause: e.to_string(),
+            status: None,
+            uri: None,
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+impl From<hyper_util::client::legacy::Error> for MyBusTrackerError {
+    fn from(e: hyper_util::client::legacy::Error) -> Self {
+        MyBusTrackerError::CommunicationError {
+            cause: e.to_string(),
+            status: None,
+            uri: None,
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+impl From<hyper::http::Error> for MyBusTrackerError {
+    fn from(e: hyper::http::Error) -> Self {
+        MyBusTrackerError::InternalError {
+            cause: e.to_string(),
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+/// Without the response body to hand, no snippet is included. Prefer mapping the error as it
+/// occurs, where the body is available.
+impl From<serde_json::Error> for MyBusTrackerError {
+    fn from(e: serde_json::Error) -> Self {
+        MyBusTrackerError::Deserialization {
+            cause: e.to_string(),
+            line: e.line(),
+            column: e.column(),
+            snippet: String::new(),
+            uri: None,
+            source: Box::new(e),
+        }
+    }
 }
 
+impl From<InvalidUri> for MyBusTrackerError {
+    fn from(e: InvalidUri) -> Self {
+        MyBusTrackerError::InternalError {
+            cause: e.to_string(),
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+impl From<url::ParseError> for MyBusTrackerError {
+    fn from(e: url::ParseError) -> Self {
+        MyBusTrackerError::InternalError {
+            cause: e.to_string(),
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+/// Callback invoked with the URI of each HTTP request, as it is made.
+pub type RequestHook = dyn Fn(&Uri) + Send + Sync;
+
+/// Callback invoked with the URI, duration and response body size (in bytes) of each
+/// successful HTTP request, once the response has been received.
+pub type ResponseHook = dyn Fn(&Uri, Duration, usize) + Send + Sync;
+
+/// The HTTP client with which API requests are made.
+pub type HttpClient<C = Connector> = Client<C, Empty<Bytes>>;
+
 /// Instance of the My Bus Tracker API.
 ///
 /// Typically, one instance of this struct will be instantiated for your entire application.
-pub struct MyBusTracker {
-    api_key: RefCell<ApiKey>,
+///
+/// An instance is `Send` and `Sync`, so it may be shared between tasks and threads (e.g. in an
+/// `Arc`). Requests must be made from within a Tokio runtime.
+///
+/// An instance may also be cloned cheaply. Clones share the API key, so it is only generated
+/// once per hour between them, as well as the HTTP client's connection pool and any requests
+/// in flight. Configuration changed on one clone afterwards does not affect the others.
+///
+/// By default, requests are made using a client with the crate's `Connector`. A client with any
+/// other connector may be supplied with `MyBusTracker::with_client`, or any other `Transport`
+/// with `MyBusTracker::set_transport`.
+#[derive(Clone)]
+pub struct MyBusTracker<C = Connector> {
+    api_key: Arc<Mutex<ApiKey>>,
     logger: Logger,
-    client: Rc<Client<HttpConnector>>,
+    backend: Backend<C>,
     root_url: Url,
+    timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    user_agent: String,
+    response_format: ResponseFormat,
+    default_operator: models::Operator,
+    in_flight: Option<Arc<coalesce::InFlight>>,
+    request_hook: Option<Arc<RequestHook>>,
+    response_hook: Option<Arc<ResponseHook>>,
+}
+
+/// The means by which a `MyBusTracker` makes its requests.
+#[derive(Clone)]
+enum Backend<C> {
+    /// An HTTP client, with the connector `C`.
+    Client(HttpClient<C>),
+    /// Any other transport, in place of an HTTP client.
+    Transport(Arc<dyn Transport>),
+}
+
+/// Compile-time check that `MyBusTracker` may be shared between threads.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<MyBusTracker>();
 }
 
 /// Holds an API Key for accessing the My Bus Tracker Web Service.
@@ -95,21 +363,34 @@ struct ApiKey {
     raw_api_key: String,
     key: String,
     generated: chrono::DateTime<Utc>,
+    clock: Box<dyn Clock>,
     logger: Logger,
 }
 
 impl ApiKey {
-    /// Create a new API key representation.
-    pub fn new(api_key: &str, logger: &Logger) -> Self {
-        trace!(logger, "Instantiating new API Key"; "api_key" => api_key);
+    /// Create a new API key representation, using the given clock to determine the time.
+    ///
+    /// Fails if the API key is empty, or consists only of whitespace.
+    pub fn new(
+        api_key: &str,
+        clock: Box<dyn Clock>,
+        logger: &Logger,
+    ) -> Result<Self, MyBusTrackerError> {
+        trace!(logger, "Instantiating new API Key"; "api_key" => mask_key(api_key));
 
-        let (key, generated) = generate_api_key(logger, api_key);
-        Self {
+        if api_key.trim().is_empty() {
+            return Err(MyBusTrackerError::EmptyApiKey);
+        }
+
+        let generated = clock.now();
+        let key = generate_api_key(logger, api_key, &generated);
+        Ok(Self {
             raw_api_key: api_key.to_owned(),
             key,
             generated,
+            clock,
             logger: logger.clone(),
-        }
+        })
     }
 
     /// Retrieve a valid key.
@@ -124,39 +405,247 @@ impl ApiKey {
         //   - Concatenating the developer API key and the current UTC time in YYYYMMDDHH format
         //   - Computing the MD5 hash of the concatenated string.
         // That means API keys are only valid for the current hour, and the system time must be
-        // accurate. We only need to recalculate the key if the hour has changed since the last
-        // request.
-        if self.generated.hour() == Utc::now().hour() {
+        // accurate. We only need to recalculate the key if the clock-hour (including the date)
+        // has changed since the last request.
+        let now = self.clock.now();
+        if same_clock_hour(&self.generated, &now) {
             trace!(
                 self.logger,
                 "Skipping API Key regeneration as time hasn't shifted enough"
             );
         } else {
-            let (key, generated) = generate_api_key(&self.logger, &self.raw_api_key);
-            self.key = key;
-            self.generated = generated;
+            self.key = generate_api_key(&self.logger, &self.raw_api_key, &now);
+            self.generated = now;
         }
         self.key.to_owned()
     }
+
+    /// Whether the current key is for the current clock-hour, i.e. `get_key` would not
+    /// regenerate it.
+    pub fn is_current(&self) -> bool {
+        same_clock_hour(&self.generated, &self.clock.now())
+    }
 }
 
 impl MyBusTracker {
     /// Create a new MyBusTracker instance.
     ///
-    /// Requires an instance of a logger, your developer API key, and a Tokio handle with which
-    /// HTTP API requests will be made.
-    pub fn new(logger: &Logger, api_key: &str, handle: &Handle) -> Result<Self, Error> {
-        trace!(logger, "Instantiating new MyBusTracker"; "api_key" => api_key);
-        let client = Client::configure().build(handle);
+    /// Requires an instance of a logger and your developer API key. Fails if the API key is
+    /// empty.
+    ///
+    /// For further configuration, use `MyBusTracker::builder` instead.
+    pub fn new(logger: &Logger, api_key: &str) -> Result<Self, MyBusTrackerError> {
+        Self::builder()
+            .logger(logger.clone())
+            .api_key(api_key)
+            .build()
+    }
 
-        let root_url = Url::parse("http://ws.mybustracker.co.uk/?module=json")?;
+    /// Create a new MyBusTracker instance, which discards all log output (or, with the `log`
+    /// feature, sends it to the `log` facade).
+    ///
+    /// Requires only your developer API key. Fails if the API key is empty.
+    pub fn without_logging(api_key: &str) -> Result<Self, MyBusTrackerError> {
+        Self::builder().api_key(api_key).build()
+    }
 
-        Ok(Self {
-            api_key: RefCell::new(ApiKey::new(api_key, logger)),
-            logger: logger.clone(),
-            client: Rc::new(client),
-            root_url,
-        })
+    /// Create a builder, with which a MyBusTracker instance can be configured.
+    pub fn builder() -> MyBusTrackerBuilder {
+        MyBusTrackerBuilder::new()
+    }
+}
+
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Create a new MyBusTracker instance, making requests with an existing HTTP client.
+    ///
+    /// Requires an instance of a logger, your developer API key, and the HTTP client. For further
+    /// configuration, use `MyBusTrackerBuilder::build_with_client` instead.
+    pub fn with_client(
+        logger: &Logger,
+        api_key: &str,
+        client: HttpClient<C>,
+    ) -> Result<Self, MyBusTrackerError> {
+        MyBusTracker::builder()
+            .logger(logger.clone())
+            .api_key(api_key)
+            .build_with_client(client)
+    }
+
+    /// Replace the base URL of the My Bus Tracker web service, e.g. to test against a mock server.
+    ///
+    /// Any query string on the base URL is retained, and the API parameters (including the
+    /// `module` parameter selecting the response format) are appended to it. A `module`
+    /// parameter already on the base URL is replaced, so older base URLs ending in
+    /// `?module=json` still work.
+    pub fn with_base_url(mut self, base_url: Url) -> Self {
+        self.root_url = base_url;
+        self
+    }
+
+    /// Set the maximum time to wait for each request to complete.
+    ///
+    /// Requests which take longer fail with `MyBusTrackerError::Timeout`. By default, there is
+    /// no timeout.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Retry requests which fail with a transient error, up to `max_retries` times.
+    ///
+    /// The first retry is made after `base_delay`, and the delay doubles for each subsequent
+    /// retry. See `MyBusTrackerError::is_retryable` for which errors are retried. By default,
+    /// requests are not retried.
+    pub fn set_retry(&mut self, max_retries: u32, base_delay: Duration) {
+        self.retry_policy = RetryPolicy::new(max_retries, base_delay);
+    }
+
+    /// Set the User-Agent sent with each request, e.g. to identify your application.
+    ///
+    /// By default, the User-Agent identifies this crate and its version.
+    pub fn set_user_agent(&mut self, user_agent: String) {
+        self.user_agent = user_agent;
+    }
+
+    /// Set the format in which the web service is asked to respond.
+    ///
+    /// By default, responses are requested as JSON.
+    pub fn set_response_format(&mut self, response_format: ResponseFormat) {
+        self.response_format = response_format;
+    }
+
+    /// Set the operator used by the `*_default` variants of the web service methods, e.g.
+    /// `get_services_default`.
+    ///
+    /// By default, this is `Operator::AllOperators`.
+    pub fn set_default_operator(&mut self, operator: models::Operator) {
+        self.default_operator = operator;
+    }
+
+    /// Set whether identical requests made concurrently should share a single HTTP request.
+    ///
+    /// Requests are identical if their URIs are, including the API key, which changes each
+    /// hour. Each caller still deserializes the response, and retries, independently. By
+    /// default, requests are not coalesced.
+    pub fn set_coalesce_requests(&mut self, coalesce: bool) {
+        self.in_flight = if coalesce {
+            Some(Arc::new(Mutex::new(HashMap::new())))
+        } else {
+            None
+        };
+    }
+
+    /// Make requests through the given transport instead of the HTTP client, e.g. a
+    /// `MockTransport` in unit tests.
+    ///
+    /// The timeout, retry policy, coalescing and hooks still apply to each request.
+    pub fn set_transport<T: Transport + 'static>(&mut self, transport: T) {
+        self.backend = Backend::Transport(Arc::new(transport));
+    }
+
+    /// The base URL of the My Bus Tracker web service.
+    pub fn base_url(&self) -> &Url {
+        &self.root_url
+    }
+
+    /// The maximum time to wait for each request to complete, if any.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// The policy by which failed requests are retried.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    /// The User-Agent sent with each request.
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// The format in which the web service is asked to respond.
+    pub fn response_format(&self) -> ResponseFormat {
+        self.response_format
+    }
+
+    /// The operator used by the `*_default` variants of the web service methods.
+    pub fn default_operator(&self) -> &models::Operator {
+        &self.default_operator
+    }
+
+    /// Whether identical requests made concurrently share a single HTTP request.
+    pub fn coalesces_requests(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Whether the cached API key is for the current clock-hour, according to the configured
+    /// clock.
+    ///
+    /// This doesn't regenerate the key, or make any request. The key is regenerated as needed
+    /// by the next request, so this is mostly useful for health checks, or for diagnosing a
+    /// drifting system clock.
+    pub fn key_is_current(&self) -> bool {
+        match self.api_key.lock() {
+            Ok(api_key) => api_key.is_current(),
+            Err(poisoned) => poisoned.into_inner().is_current(),
+        }
+    }
+
+    /// Set a callback to be invoked as each HTTP request is made, e.g. for instrumentation.
+    ///
+    /// Retried requests invoke the callback once per attempt.
+    pub fn on_request<F>(&mut self, hook: F)
+    where
+        F: Fn(&Uri) + Send + Sync + 'static,
+    {
+        self.request_hook = Some(Arc::new(hook));
+    }
+
+    /// Set a callback to be invoked as each successful HTTP response is received, e.g. for
+    /// instrumentation.
+    ///
+    /// The callback is given the request URI, the time taken, and the size of the response body
+    /// in bytes.
+    pub fn on_response<F>(&mut self, hook: F)
+    where
+        F: Fn(&Uri, Duration, usize) + Send + Sync + 'static,
+    {
+        self.response_hook = Some(Arc::new(hook));
+    }
+
+    /// Call the given API function, returning the untyped response.
+    ///
+    /// This is an escape hatch for accessing functions, or fields, not yet supported by the
+    /// models in this crate. If the URL parameters are specified, they must already be encoded
+    /// as URI parameters (i.e. URL encoded key=value format, and separated with ampersands).
+    pub async fn get_raw(
+        &self,
+        function: &str,
+        uri_params: Option<&str>,
+    ) -> Result<serde_json::Value, MyBusTrackerError> {
+        debug!(
+            self.logger,
+            "Getting raw response";
+            "function" => function,
+            "params" => ?uri_params,
+        );
+        let uri = self.get_uri(function, uri_params)?;
+
+        self.make_request(uri).await
+    }
+
+    /// The current time, according to the configured clock.
+    #[cfg(feature = "chrono-tz")]
+    fn now(&self) -> Result<chrono::DateTime<Utc>, MyBusTrackerError> {
+        let api_key = self.api_key
+            .lock()
+            .map_err(|e| MyBusTrackerError::InternalError {
+                cause: e.to_string(),
+                source: None,
+            })?;
+        Ok(api_key.clock.now())
     }
 
     /// Return the URI to hit for the given API function with the given URL parameters.
@@ -165,81 +654,337 @@ impl MyBusTracker {
     /// (i.e. URL encoded key=value format, and separated with ampersands)
     fn get_uri(&self, function: &str, uri_params: Option<&str>) -> Result<Uri, MyBusTrackerError> {
         trace!(self.logger, "Figuring out URI"; "function" => function, "params" => ?uri_params);
-        let api_key = self.api_key.borrow_mut().get_key();
+        let api_key = self.api_key
+            .lock()
+            .map_err(|e| MyBusTrackerError::InternalError {
+                cause: e.to_string(),
+                source: None,
+            })?
+            .get_key();
+        let module = self.response_format;
         let merged_params = match uri_params {
-            None => format!("key={}&function={}", api_key, function),
-            Some(params) => format!("key={}&function={}&{}", api_key, function, params),
+            None => format!("module={}&key={}&function={}", module, api_key, function),
+            Some(params) => format!(
+                "module={}&key={}&function={}&{}",
+                module, api_key, function, params
+            ),
         };
 
-        let query = self.root_url.query();
-        let query_string = match query {
-            None => merged_params,
-            Some(query_string) => format!("{}&{}", query_string, merged_params),
+        // Any `module` parameter of the base URL is replaced by that of the response format.
+        let base_params: Vec<&str> = self.root_url
+            .query()
+            .unwrap_or("")
+            .split('&')
+            .filter(|pair| !pair.is_empty() && *pair != "module" && !pair.starts_with("module="))
+            .collect();
+        let query_string = if base_params.is_empty() {
+            merged_params
+        } else {
+            format!("{}&{}", base_params.join("&"), merged_params)
         };
 
         let mut uri = self.root_url.clone();
         uri.set_query(Some(&query_string));
-        uri.into_string()
-            .parse()
-            .map_err(|e: UriError| MyBusTrackerError::InternalError {
-                cause: e.to_string(),
-            })
+        Ok(uri.into_string().parse::<Uri>()?)
     }
 
-    /// Performs the given HTTP request, deserializing the result into the requested type `T`.
-    fn make_request<T: 'static>(
-        &self,
-        mut request: Request,
-    ) -> Box<Future<Item = T, Error = MyBusTrackerError>>
+    /// Performs an HTTP GET request for the given URI, deserializing the result into the
+    /// requested type `T`.
+    ///
+    /// Failed requests are retried according to the configured retry policy. With the `tracing`
+    /// feature, the whole request is made within a `tracing` span.
+    async fn make_request<T>(&self, uri: Uri) -> Result<T, MyBusTrackerError>
     where
         T: serde::de::DeserializeOwned,
     {
-        trace!(self.logger, "Performing HTTP request"; "uri" => ?request.uri());
-
-        let client = self.client.clone();
-
-        let useragent_header = UserAgent::new(format!(
-            "{}/{}",
-            APP_NAME.unwrap_or("my_bus_tracker_rs"),
-            APP_VERSION.unwrap_or("unknown")
-        ));
-        request.headers_mut().set(useragent_header);
-
-        Box::new(
-            client
-                .request(request)
-                .map_err(|e| MyBusTrackerError::CommunicationError {
-                    cause: e.to_string(),
-                })
-                .and_then(|res| {
-                    res.body()
-                        .fold(Vec::new(), |mut v, chunk| {
-                            v.extend(&chunk[..]);
-                            ok::<_, hyper::Error>(v)
-                        })
-                        .map_err(|e| MyBusTrackerError::InternalError {
-                            cause: e.to_string(),
-                        })
-                })
-                .and_then(move |chunks| {
-                    serde_json::from_slice(&chunks).map_err(|e| MyBusTrackerError::InternalError {
-                        cause: e.to_string(),
-                    })
-                }),
-        )
+        #[cfg(feature = "tracing")]
+        let request = tracing_span::instrument(&uri, self.perform_request(uri.clone()));
+        #[cfg(not(feature = "tracing"))]
+        let request = self.perform_request(uri);
+        request.await
+    }
+
+    /// Performs the request for `make_request`, retrying failures and deserializing the result.
+    async fn perform_request<T>(&self, uri: Uri) -> Result<T, MyBusTrackerError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        trace!(self.logger, "Performing HTTP request"; "uri" => redact_uri(&uri));
+
+        let mut attempt = 0;
+        let chunks = loop {
+            if let Some(ref hook) = self.request_hook {
+                hook(&uri);
+            }
+            let started = Instant::now();
+
+            let result = match self.in_flight {
+                Some(ref in_flight) => {
+                    coalesce::fetch(in_flight, uri.clone(), || self.request(uri.clone())).await
+                }
+                None => self.request(uri.clone()).await,
+            };
+            match result {
+                Ok(chunks) => {
+                    if let Some(ref hook) = self.response_hook {
+                        hook(&uri, started.elapsed(), chunks.len());
+                    }
+                    break chunks;
+                }
+                Err(ref e) if self.retry_policy.should_retry(attempt, e) => {
+                    let delay = self.retry_policy.delay(attempt);
+                    debug!(self.logger, "Retrying failed request";
+                           "attempt" => attempt + 1, "delay" => ?delay, "error" => %e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.with_uri(&uri)),
+            }
+        };
+
+        deserialize(self.response_format, &chunks).map_err(|e| e.with_uri(&uri))
+    }
+
+    /// A single HTTP GET request for the given URI through the HTTP client or transport,
+    /// subject to the request timeout.
+    fn request(&self, uri: Uri) -> BoxFuture<'static, Result<Vec<u8>, MyBusTrackerError>> {
+        let backend = self.backend.clone();
+        let timeout = self.timeout;
+        let user_agent = self.user_agent.clone();
+        async move {
+            let response = match backend {
+                Backend::Client(ref client) => Transport::get(client, &uri, &user_agent),
+                Backend::Transport(ref transport) => transport.get(&uri, &user_agent),
+            };
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, response)
+                    .await
+                    .map_err(|_| MyBusTrackerError::Timeout)?,
+                None => response.await,
+            }
+        }.boxed()
+    }
+}
+
+/// Deserializes a response body in the given format into the requested type `T`.
+///
+/// Fails with `MyBusTrackerError::EmptyResponse` if the body is empty, or only whitespace.
+fn deserialize<T>(format: ResponseFormat, body: &[u8]) -> Result<T, MyBusTrackerError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Err(MyBusTrackerError::EmptyResponse);
+    }
+
+    match format {
+        ResponseFormat::Json => {
+            serde_json::from_slice(body).map_err(|e| MyBusTrackerError::Deserialization {
+                cause: e.to_string(),
+                line: e.line(),
+                column: e.column(),
+                snippet: snippet_around(body, e.line(), e.column()),
+                uri: None,
+                source: Box::new(e),
+            })
+        }
+        // The XML deserializer doesn't report where it failed, so the snippet is taken from
+        // the start of the body.
+        #[cfg(feature = "xml")]
+        ResponseFormat::Xml => {
+            serde_xml_rs::from_reader(body).map_err(|e| MyBusTrackerError::Deserialization {
+                cause: e.to_string(),
+                line: 1,
+                column: 1,
+                snippet: snippet_around(body, 1, 1),
+                uri: None,
+                source: Box::new(e),
+            })
+        }
+    }
+}
+
+/// Performs a single HTTP GET request for the given URI, returning the response body.
+async fn fetch<C>(
+    client: &HttpClient<C>,
+    user_agent: &str,
+    uri: Uri,
+) -> Result<Vec<u8>, MyBusTrackerError>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let request = Request::get(uri).header(USER_AGENT, user_agent);
+    #[cfg(feature = "compression")]
+    let request = request.header(ACCEPT_ENCODING, compression::ACCEPT_ENCODING);
+    let request = request.body(Empty::new())?;
+
+    let res = client.request(request).await?;
+    let status = res.status();
+    #[cfg(feature = "compression")]
+    let content_encoding = res.headers().get(CONTENT_ENCODING).cloned();
+    let chunks = read_body(res).await?;
+
+    if status.is_success() {
+        #[cfg(feature = "compression")]
+        let chunks = compression::decode(content_encoding.as_ref(), chunks)?;
+        return Ok(chunks);
+    }
+    // The status is reported even if the error body can't be decompressed.
+    #[cfg(feature = "compression")]
+    let chunks = compression::decode(content_encoding.as_ref(), chunks.clone()).unwrap_or(chunks);
+    let snippet_length = chunks.len().min(ERROR_BODY_SNIPPET_LENGTH);
+    Err(MyBusTrackerError::CommunicationError {
+        cause: format!(
+            "HTTP {}: {}",
+            status,
+            String::from_utf8_lossy(&chunks[..snippet_length])
+        ),
+        status: Some(status.as_u16()),
+        uri: None,
+        source: None,
+    })
+}
+
+/// Reads the whole body of a response.
+///
+/// If the response gives its `Content-Length`, the buffer is allocated up front (up to
+/// `MAX_BODY_PREALLOCATION` bytes), rather than grown as the body arrives.
+async fn read_body(response: Response<Incoming>) -> Result<Vec<u8>, MyBusTrackerError> {
+    let capacity = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .map_or(0, |length| length.min(MAX_BODY_PREALLOCATION));
+
+    let mut body = response.into_body();
+    let mut chunks = Vec::with_capacity(capacity);
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame?.into_data() {
+            chunks.extend_from_slice(&data);
+        }
+    }
+    Ok(chunks)
+}
+
+/// Returns a snippet of the given body, centred on the given (1-based) line and column.
+fn snippet_around(body: &[u8], line: usize, column: usize) -> String {
+    let line_start: usize = body.split(|&b| b == b'\n')
+        .take(line.saturating_sub(1))
+        .map(|l| l.len() + 1)
+        .sum();
+    let offset = (line_start + column.saturating_sub(1)).min(body.len());
+
+    let start = offset.saturating_sub(ERROR_BODY_SNIPPET_LENGTH / 2);
+    let end = (start + ERROR_BODY_SNIPPET_LENGTH).min(body.len());
+    String::from_utf8_lossy(&body[start..end]).into_owned()
+}
+
+/// The logger used if none is given, which forwards to the `log` facade.
+#[cfg(feature = "log")]
+fn default_logger() -> Logger {
+    Logger::root(log_facade::LogDrain, o!())
+}
+
+/// The logger used if none is given, which discards all output.
+#[cfg(not(feature = "log"))]
+fn default_logger() -> Logger {
+    Logger::root(slog::Discard, o!())
+}
+
+/// A masked form of an API key, safe to log, showing only its first and last two characters.
+///
+/// Keys of four characters or fewer are masked entirely.
+fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 4 {
+        return "*".repeat(chars.len());
     }
+    let head: String = chars[..2].iter().collect();
+    let tail: String = chars[chars.len() - 2..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+/// The given URI, with the value of its `key` query parameter (the API key) redacted.
+fn redact_uri(uri: &Uri) -> String {
+    let query = match uri.query() {
+        Some(query) => query,
+        None => return uri.to_string(),
+    };
+    let query = query
+        .split('&')
+        .map(|pair| if pair.starts_with("key=") { "key=REDACTED" } else { pair })
+        .collect::<Vec<&str>>()
+        .join("&");
+
+    let mut redacted = uri.to_string();
+    if let Some(start) = redacted.find('?') {
+        redacted.truncate(start + 1);
+        redacted.push_str(&query);
+    }
+    redacted
+}
+
+/// The API function requested by a URI, i.e. its `function` query parameter.
+///
+/// Returns an empty string if there is no such parameter.
+fn api_function(uri: &Uri) -> String {
+    url::form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+        .find(|(key, _)| key == "function")
+        .map(|(_, function)| function.into_owned())
+        .unwrap_or_default()
+}
+
+/// The default User-Agent, identifying this crate and its version.
+fn default_user_agent() -> String {
+    format!(
+        "{}/{}",
+        APP_NAME.unwrap_or("my_bus_tracker_rs"),
+        APP_VERSION.unwrap_or("unknown")
+    )
+}
+
+/// Create a new connector for the HTTP client.
+#[cfg(feature = "tls")]
+fn new_connector() -> Connector {
+    HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .build()
+}
+
+/// Create a new connector for the HTTP client.
+#[cfg(not(feature = "tls"))]
+fn new_connector() -> Connector {
+    HttpConnector::new()
+}
+
+/// Create a new HTTP client, making requests with the given connector.
+fn new_client<C>(connector: C) -> HttpClient<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    Client::builder(TokioExecutor::new())
+        .pool_timer(TokioTimer::new())
+        .build(connector)
+}
+
+/// Whether two instants fall within the same UTC clock-hour, i.e. share a `YYYYMMDDHH` stamp.
+fn same_clock_hour(a: &chrono::DateTime<Utc>, b: &chrono::DateTime<Utc>) -> bool {
+    a.date_naive() == b.date_naive() && a.hour() == b.hour()
 }
 
-/// Take a base API key and turn it into a My Bus Tracker API key, valid for the clock-hour.
-fn generate_api_key(logger: &Logger, base_key: &str) -> (String, chrono::DateTime<Utc>) {
-    debug!(logger, "Generating API key"; "base_key" => base_key);
+/// Take a base API key and turn it into a My Bus Tracker API key, valid for the clock-hour of
+/// the given time.
+fn generate_api_key(logger: &Logger, base_key: &str, time: &chrono::DateTime<Utc>) -> String {
+    debug!(logger, "Generating API key"; "base_key" => mask_key(base_key));
 
     // Per the MyBusTracker WS API Guide (Version F), the generated API key is formed by:
     //   - Concatenating the developer API key and the current UTC time in YYYYMMDDHH format
     //   - Computing the MD5 hash of the concatenated string.
     // That means API keys are only valid for the current hour, and the system time must be
     // accurate.
-    let time = Utc::now();
     let time_string = time.format("%Y%m%d%H");
 
     let raw_key = format!("{}{}", base_key, time_string);
@@ -248,6 +993,611 @@ fn generate_api_key(logger: &Logger, base_key: &str) -> (String, chrono::DateTim
     let computed_key_string = format!("{:x}", computed_key);
 
     trace!(logger, "Computed API Key";
-           "base_key" => base_key, "time" => %time_string, "computed_key" => %computed_key_string);
-    (computed_key_string, time)
+           "base_key" => mask_key(base_key), "time" => %time_string,
+           "computed_key" => mask_key(&computed_key_string));
+    computed_key_string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock which can be moved to any time, shared between clones.
+    #[derive(Clone)]
+    pub(crate) struct TestClock(Arc<Mutex<chrono::DateTime<Utc>>>);
+
+    impl TestClock {
+        pub(crate) fn at(time: chrono::DateTime<Utc>) -> Self {
+            TestClock(Arc::new(Mutex::new(time)))
+        }
+
+        pub(crate) fn set(&self, time: chrono::DateTime<Utc>) {
+            *self.0.lock().unwrap() = time;
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> chrono::DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    /// A drain recording each record as its message followed by ` key=value` for each pair.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Capture {
+        fn lines(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl slog::Drain for Capture {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(&self, record: &slog::Record, values: &slog::OwnedKVList) -> Result<(), slog::Never> {
+            use slog::KV;
+
+            let mut line = Pairs(record.msg().to_string());
+            record.kv().serialize(record, &mut line).unwrap();
+            values.serialize(record, &mut line).unwrap();
+            self.0.lock().unwrap().push(line.0);
+            Ok(())
+        }
+    }
+
+    struct Pairs(String);
+
+    impl slog::Serializer for Pairs {
+        fn emit_arguments(&mut self, key: slog::Key, value: &std::fmt::Arguments) -> slog::Result {
+            self.0.push_str(&format!(" {}={}", key, value));
+            Ok(())
+        }
+    }
+
+    /// Serve the given raw HTTP response to every connection on a local port, returning the
+    /// base URL to reach it.
+    pub(crate) async fn serve(response: &'static [u8]) -> Url {
+        serve_with(move |_| response.to_vec()).await
+    }
+
+    /// Serve the raw HTTP response built from each request's head, as `serve`.
+    pub(crate) async fn serve_with<F>(respond: F) -> Url
+    where
+        F: Fn(&str) -> Vec<u8> + Send + 'static,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let response = respond(&String::from_utf8_lossy(&request));
+                let _ = stream.write_all(&response).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        Url::parse(&format!("http://{}/", address)).unwrap()
+    }
+
+    /// Serve the given body as a successful JSON response, as `serve`.
+    pub(crate) async fn serve_json(body: &str) -> Url {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        serve(Box::leak(response.into_boxed_str()).as_bytes()).await
+    }
+
+    fn tracker_for(base_url: Url) -> MyBusTracker {
+        MyBusTracker::new(&logger(), "abc")
+            .unwrap()
+            .with_base_url(base_url)
+    }
+
+    #[test]
+    fn api_key_is_regenerated_at_the_same_hour_on_another_day() {
+        let yesterday = Utc.with_ymd_and_hms(2018, 1, 1, 3, 15, 0).unwrap();
+        let today = yesterday + chrono::Duration::hours(24);
+        let clock = TestClock::at(yesterday);
+        let mut api_key = ApiKey::new("abc", Box::new(clock.clone()), &logger()).unwrap();
+        let yesterdays_key = api_key.get_key();
+
+        clock.set(today);
+        let todays_key = api_key.get_key();
+
+        assert_ne!(yesterdays_key, todays_key);
+        assert_eq!(todays_key, generate_api_key(&logger(), "abc", &today));
+    }
+
+    #[test]
+    fn api_key_is_reused_within_the_same_hour() {
+        let generated = Utc.with_ymd_and_hms(2018, 1, 2, 3, 0, 0).unwrap();
+        let clock = TestClock::at(generated);
+        let mut api_key = ApiKey::new("abc", Box::new(clock.clone()), &logger()).unwrap();
+        let key = api_key.get_key();
+
+        clock.set(generated + chrono::Duration::minutes(59));
+        assert_eq!(api_key.get_key(), key);
+    }
+
+    #[test]
+    fn base_url_query_is_kept_before_the_api_parameters() {
+        let base_url = Url::parse("http://localhost:8080/ws?token=xyz").unwrap();
+        let tracker = MyBusTracker::new(&logger(), "abc")
+            .unwrap()
+            .with_base_url(base_url);
+        let uri = tracker.get_uri("getServices", Some("operatorId=LB")).unwrap();
+
+        assert_eq!(uri.host(), Some("localhost"));
+        assert_eq!(uri.port_u16(), Some(8080));
+        assert_eq!(uri.path(), "/ws");
+        let query = uri.query().unwrap();
+        assert!(query.starts_with("token=xyz&module=json&key="), "{}", query);
+        assert!(query.ends_with("&function=getServices&operatorId=LB"), "{}", query);
+    }
+
+    #[tokio::test]
+    async fn server_errors_keep_their_status() {
+        let base_url = serve(b"HTTP/1.1 500 Internal Server Error\r\n\
+                               Content-Length: 5\r\nConnection: close\r\n\r\noops!")
+            .await;
+        let error = tracker_for(base_url)
+            .get_raw("getTopoId", None)
+            .await
+            .unwrap_err();
+
+        match error {
+            MyBusTrackerError::CommunicationError { status, ref cause, .. } => {
+                assert_eq!(status, Some(500));
+                assert!(cause.contains("500"), "{}", cause);
+                assert!(cause.contains("oops!"), "{}", cause);
+            }
+            ref e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn tracker_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MyBusTracker>();
+    }
+
+    #[tokio::test]
+    async fn requests_to_an_unresponsive_server_time_out() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+        let mut tracker = tracker_for(base_url);
+        tracker.set_timeout(Duration::from_millis(100));
+
+        let error = tracker.get_raw("getTopoId", None).await.unwrap_err();
+        assert!(matches!(error, MyBusTrackerError::Timeout), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn requests_are_made_with_a_supplied_client() {
+        let base_url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\
+                               Connection: close\r\n\r\n\
+                               {\"topoId\": \"20180101\", \"operatorId\": \"LB\"}")
+            .await;
+        let client = new_client(HttpConnector::new());
+        let tracker = MyBusTracker::with_client(&logger(), "abc", client)
+            .unwrap()
+            .with_base_url(base_url);
+
+        let response = tracker.get_raw("getTopoId", None).await.unwrap();
+        assert_eq!(response["topoId"], "20180101");
+    }
+
+    #[test]
+    fn api_key_is_the_md5_of_the_key_and_clock_hour() {
+        let time = Utc.with_ymd_and_hms(2018, 1, 2, 3, 45, 0).unwrap();
+        assert_eq!(
+            generate_api_key(&logger(), "abc", &time),
+            "9558f0bfce3d0f9bdfa98b1e5102791c"
+        );
+    }
+
+    #[test]
+    fn deserialization_errors_give_their_location() {
+        let body = b"{\n  \"topoId\": 20180101,\n  \"operatorId\": \"LB\"\n}";
+        let error = deserialize::<models::TopoId>(ResponseFormat::Json, body).unwrap_err();
+
+        match error {
+            MyBusTrackerError::Deserialization {
+                line,
+                column,
+                ref snippet,
+                ..
+            } => {
+                assert_eq!(line, 2);
+                assert!(column > 1);
+                assert!(snippet.contains("\"topoId\": 20180101"), "{}", snippet);
+            }
+            ref e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_sent_with_the_user_agent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let mut tracker = tracker_for(base_url);
+        tracker.set_user_agent("test-agent/1.0".to_owned());
+
+        tracker.get_raw("getTopoId", None).await.unwrap();
+        let request = server.await.unwrap().to_lowercase();
+        assert!(request.contains("user-agent: test-agent/1.0\r\n"), "{}", request);
+    }
+
+    #[test]
+    fn module_parameter_of_the_base_url_is_replaced() {
+        let base_url = Url::parse("http://localhost:8080/?module=json&token=xyz").unwrap();
+        let mut tracker = tracker_for(base_url);
+        let uri = tracker.get_uri("getTopoId", None).unwrap();
+        let query = uri.query().unwrap();
+        assert!(query.starts_with("token=xyz&module=json&key="), "{}", query);
+        assert_eq!(query.matches("module=").count(), 1, "{}", query);
+
+        tracker.root_url = Url::parse("http://localhost:8080/?module=json").unwrap();
+        let uri = tracker.get_uri("getTopoId", None).unwrap();
+        assert!(uri.query().unwrap().starts_with("module=json&key="), "{}", uri);
+    }
+
+    #[cfg(feature = "xml")]
+    #[test]
+    fn xml_responses_are_deserialized() {
+        let body = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <result>\
+              <services>\
+                <ref>1</ref><operatorId>LB</operatorId><mnemo>1</mnemo>\
+                <name>Clermiston - Seafield</name><dests>1A</dests><dests>1B</dests>\
+              </services>\
+              <services>\
+                <ref>2</ref><operatorId>EDT</operatorId><mnemo>T50</mnemo>\
+                <name>Tram</name><dests>2A</dests>\
+              </services>\
+            </result>";
+        let services: models::Services = deserialize(ResponseFormat::Xml, body).unwrap();
+
+        assert_eq!(services.services.len(), 2);
+        assert_eq!(services.services[0].operator_id, models::Operator::LothianBuses);
+        assert_eq!(services.services[0].destinations, vec!["1A", "1B"]);
+        assert_eq!(
+            services.services[1].operator_id,
+            models::Operator::Other("EDT".to_owned())
+        );
+    }
+
+    #[test]
+    fn empty_api_keys_are_rejected() {
+        for api_key in &["", "  \t"] {
+            let error = ApiKey::new(api_key, Box::new(SystemClock), &logger()).err();
+            assert!(matches!(error, Some(MyBusTrackerError::EmptyApiKey)), "{:?}", error);
+            assert!(MyBusTracker::new(&logger(), api_key).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_are_invoked_for_each_request_and_response() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let base_url =
+            serve(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}").await;
+        let mut tracker = tracker_for(base_url);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let response_sizes = Arc::new(Mutex::new(Vec::new()));
+        let counter = Arc::clone(&requests);
+        tracker.on_request(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let sizes = Arc::clone(&response_sizes);
+        tracker.on_response(move |_, _, size| sizes.lock().unwrap().push(size));
+
+        tracker.get_raw("getTopoId", None).await.unwrap();
+        tracker.root_url = serve(
+            b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ).await;
+        tracker.get_raw("getTopoId", None).await.unwrap_err();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(*response_sizes.lock().unwrap(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn raw_responses_are_passed_through_untyped() {
+        let base_url = serve(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 58\r\n\r\n\
+              {\"busStops\": [{\"stopId\": \"36232626\", \"newField\": [1, 2]}]}",
+        ).await;
+        let mut tracker = tracker_for(base_url);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |uri| recorded.lock().unwrap().push(uri.clone()));
+
+        let response = tracker
+            .get_raw("getBusStops", Some("operatorId=LB"))
+            .await
+            .unwrap();
+
+        assert_eq!(response["busStops"][0]["newField"][1], 2);
+        let uri = &requests.lock().unwrap()[0];
+        assert!(uri.query().unwrap().ends_with("&operatorId=LB"), "{}", uri);
+    }
+
+    #[test]
+    fn underlying_errors_are_kept_as_sources() {
+        use std::error::Error as _;
+
+        let error = MyBusTrackerError::from(Url::parse("not a url").unwrap_err());
+        assert!(matches!(error, MyBusTrackerError::InternalError { .. }));
+        let source = error.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<url::ParseError>(),
+            Some(&url::ParseError::RelativeUrlWithoutBase)
+        );
+
+        let error = MyBusTrackerError::from("not a uri".parse::<Uri>().unwrap_err());
+        assert!(error.source().unwrap().downcast_ref::<InvalidUri>().is_some());
+
+        let error = MyBusTrackerError::from(serde_json::from_str::<u8>("[").unwrap_err());
+        assert!(error.source().unwrap().downcast_ref::<serde_json::Error>().is_some());
+
+        assert!(MyBusTrackerError::Timeout.source().is_none());
+    }
+
+    #[tokio::test]
+    async fn trackers_without_logging_can_be_used() {
+        let base_url = serve_json(r#"{"topoId": "20180101", "operatorId": "LB"}"#).await;
+        let tracker = MyBusTracker::without_logging("abc")
+            .unwrap()
+            .with_base_url(base_url);
+
+        let topo_id = tracker.get_topo_id(&models::Operator::LothianBuses).await.unwrap();
+        assert_eq!(topo_id.topo_id, "20180101");
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn compressed_responses_are_decoded() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(br#"{"topoId": "20180101", "operatorId": "LB"}"#).unwrap();
+        let body = encoder.finish().unwrap();
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(&body);
+
+        let base_url = serve(Box::leak(response.into_boxed_slice())).await;
+        let topo_id = tracker_for(base_url)
+            .get_topo_id(&models::Operator::LothianBuses)
+            .await
+            .unwrap();
+        assert_eq!(topo_id.topo_id, "20180101");
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn error_statuses_are_reported_before_decompression() {
+        let base_url = serve(b"HTTP/1.1 503 Service Unavailable\r\nContent-Encoding: gzip\r\n\
+                               Content-Length: 5\r\nConnection: close\r\n\r\noops!")
+            .await;
+        let error = tracker_for(base_url)
+            .get_raw("getTopoId", None)
+            .await
+            .unwrap_err();
+
+        match error {
+            MyBusTrackerError::CommunicationError { status, ref cause, .. } => {
+                assert_eq!(status, Some(503));
+                assert!(cause.contains("oops!"), "{}", cause);
+            }
+            ref e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn bodies_are_assembled_with_and_without_a_content_length() {
+        let with_length = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\
+                                  Connection: close\r\n\r\n\
+                                  {\"topoId\": \"20180101\", \"operatorId\": \"LB\"}")
+            .await;
+        let chunked = serve(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\
+                              Connection: close\r\n\r\n\
+                              14\r\n{\"topoId\": \"20180101\r\n\
+                              16\r\n\", \"operatorId\": \"LB\"}\r\n\
+                              0\r\n\r\n")
+            .await;
+
+        for base_url in [with_length, chunked] {
+            let response = tracker_for(base_url).get_raw("getTopoId", None).await.unwrap();
+            assert_eq!(response["topoId"], "20180101");
+            assert_eq!(response["operatorId"], "LB");
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_responses_fail_clearly() {
+        for body in ["", " \r\n\t"] {
+            let tracker = tracker_for(serve_json(body).await);
+
+            let error = tracker.get_topo_id(&models::Operator::LothianBuses).await.unwrap_err();
+            assert!(matches!(error, MyBusTrackerError::EmptyResponse), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn configuration_is_read_back_after_setting() {
+        let base_url = Url::parse("http://localhost:8080/api/").unwrap();
+        let mut tracker = MyBusTracker::without_logging("abc")
+            .unwrap()
+            .with_base_url(base_url.clone());
+        tracker.set_timeout(Duration::from_secs(5));
+        tracker.set_retry(3, Duration::from_millis(250));
+        tracker.set_user_agent("my-app/1.0".to_owned());
+        tracker.set_default_operator(models::Operator::LothianBuses);
+        tracker.set_coalesce_requests(true);
+
+        assert_eq!(*tracker.base_url(), base_url);
+        assert_eq!(tracker.timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(tracker.retry_policy().max_retries, 3);
+        assert_eq!(tracker.retry_policy().base_delay, Duration::from_millis(250));
+        assert_eq!(tracker.user_agent(), "my-app/1.0");
+        assert_eq!(*tracker.default_operator(), models::Operator::LothianBuses);
+        assert!(tracker.coalesces_requests());
+    }
+
+    #[tokio::test]
+    async fn a_transport_replaces_the_http_client() {
+        let transport = MockTransport::new()
+            .with_response("getTopoId", r#"{"topoId": "20180101", "operatorId": "LB"}"#);
+        let tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .transport(transport)
+            .build()
+            .unwrap();
+        assert!(matches!(tracker.backend, Backend::Transport(_)));
+
+        let topo_id = tracker.get_topo_id(&models::Operator::LothianBuses).await.unwrap();
+        assert_eq!(topo_id.topo_id, "20180101");
+
+        let tracker = MyBusTracker::builder().api_key("abc").build().unwrap();
+        assert!(matches!(tracker.backend, Backend::Client(_)));
+    }
+
+    #[test]
+    fn clones_share_the_generated_api_key() {
+        let generated = Utc.with_ymd_and_hms(2018, 1, 2, 3, 0, 0).unwrap();
+        let clock = TestClock::at(generated);
+        let tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let clone = tracker.clone();
+
+        clock.set(generated + chrono::Duration::hours(1));
+        assert!(!clone.key_is_current());
+        let uri = tracker.get_uri("getServices", None).unwrap();
+        assert!(clone.key_is_current());
+
+        let key = generate_api_key(&logger(), "abc", &(generated + chrono::Duration::hours(1)));
+        assert!(uri.query().unwrap().contains(&format!("key={}", key)), "{}", uri);
+        assert_eq!(clone.api_key.lock().unwrap().key, key);
+    }
+
+    #[test]
+    fn key_is_current_only_within_its_clock_hour() {
+        let now = Utc.with_ymd_and_hms(2018, 1, 2, 3, 45, 0).unwrap();
+        let tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .clock(TestClock::at(now))
+            .build()
+            .unwrap();
+        let set_generated = |generated| tracker.api_key.lock().unwrap().generated = generated;
+
+        set_generated(Utc.with_ymd_and_hms(2018, 1, 2, 3, 0, 0).unwrap());
+        assert!(tracker.key_is_current());
+
+        set_generated(Utc.with_ymd_and_hms(2018, 1, 2, 2, 59, 59).unwrap());
+        assert!(!tracker.key_is_current());
+
+        set_generated(Utc.with_ymd_and_hms(2018, 1, 1, 3, 15, 0).unwrap());
+        assert!(!tracker.key_is_current());
+        assert_eq!(
+            tracker.api_key.lock().unwrap().generated,
+            Utc.with_ymd_and_hms(2018, 1, 1, 3, 15, 0).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_requests_give_their_uri_with_the_key_redacted() {
+        let operator = models::Operator::LothianBuses;
+        let mut tracker = MyBusTracker::without_logging("abc").unwrap();
+        tracker.set_transport(MockTransport::new());
+
+        let error = tracker.get_topo_id(&operator).await.unwrap_err();
+        assert!(matches!(error, MyBusTrackerError::CommunicationError { .. }), "{:?}", error);
+        let key = tracker.api_key.lock().unwrap().key.clone();
+        let uri = error.uri().unwrap();
+        assert!(uri.contains("function=getTopoId"), "{}", uri);
+        assert!(uri.contains("key=REDACTED"), "{}", uri);
+        assert!(!uri.contains(&key), "{}", uri);
+
+        tracker.set_transport(MockTransport::new().with_response("getTopoId", "not json"));
+        let error = tracker.get_topo_id(&operator).await.unwrap_err();
+        assert!(matches!(error, MyBusTrackerError::Deserialization { .. }), "{:?}", error);
+        let uri = error.uri().unwrap();
+        assert!(uri.contains("key=REDACTED"), "{}", uri);
+        assert!(!uri.contains(&key), "{}", uri);
+
+        assert_eq!(MyBusTrackerError::MissingStopId.uri(), None);
+    }
+
+    #[test]
+    fn api_keys_are_masked() {
+        assert_eq!(mask_key("abcdefgh"), "ab...gh");
+        assert_eq!(mask_key("abcde"), "ab...de");
+        assert_eq!(mask_key("abcd"), "****");
+        assert_eq!(mask_key(""), "");
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_not_logged() {
+        let capture = Capture::default();
+        let logger = Logger::root(capture.clone(), o!());
+        let mut tracker = MyBusTracker::new(&logger, "secretdevkey").unwrap();
+        tracker.set_transport(MockTransport::new().with_response("getTopoId", "{}"));
+
+        tracker.get_raw("getTopoId", None).await.unwrap();
+
+        let key = tracker.api_key.lock().unwrap().key.clone();
+        let lines = capture.lines();
+        assert!(lines.iter().any(|line| line.contains("base_key=se...ey")), "{:?}", lines);
+        for line in &lines {
+            assert!(!line.contains("secretdevkey"), "{}", line);
+            assert!(!line.contains(&key), "{}", line);
+        }
+    }
 }
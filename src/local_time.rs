@@ -0,0 +1,87 @@
+//! Local Times
+//!
+//! Times are given by the web service in UTC, which remains the source of truth. These
+//! accessors convert them to Edinburgh local time (Europe/London, so GMT or BST as applicable),
+//! for display. Requires the `chrono-tz` feature.
+
+use chrono::DateTime;
+use chrono_tz::Europe::London;
+use chrono_tz::Tz;
+
+use super::models::{Disruption, Diversion};
+
+impl Disruption {
+    /// The time until which the disruption is valid, in Europe/London local time.
+    pub fn valid_until_local(&self) -> Option<DateTime<Tz>> {
+        self.valid_until
+            .map(|valid_until| valid_until.with_timezone(&London))
+    }
+}
+
+impl Diversion {
+    /// The start of the diversion, in Europe/London local time.
+    pub fn start_date_local(&self) -> DateTime<Tz> {
+        self.start_date.with_timezone(&London)
+    }
+
+    /// The end of the diversion, in Europe/London local time.
+    pub fn end_date_local(&self) -> DateTime<Tz> {
+        self.end_date.with_timezone(&London)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Diversions;
+
+    fn disruption(valid_until: &str) -> Disruption {
+        serde_json::from_str(&format!(
+            r#"{{"id": "d1", "operatorId": "LB", "level": 1, "type": 0, "targets": [],
+                 "validUntil": {}, "message": ""}}"#,
+            valid_until
+        ))
+        .unwrap()
+    }
+
+    fn local(disruption: &Disruption) -> String {
+        disruption
+            .valid_until_local()
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S %Z")
+            .to_string()
+    }
+
+    #[test]
+    fn local_times_follow_the_daylight_saving_transitions() {
+        let cases = [
+            ("2018-03-25T00:59:59Z", "2018-03-25 00:59:59 GMT"),
+            ("2018-03-25T01:00:00Z", "2018-03-25 02:00:00 BST"),
+            ("2018-10-28T00:59:59Z", "2018-10-28 01:59:59 BST"),
+            ("2018-10-28T01:00:00Z", "2018-10-28 01:00:00 GMT"),
+        ];
+        for &(utc, expected) in &cases {
+            assert_eq!(local(&disruption(&format!("\"{}\"", utc))), expected, "{}", utc);
+        }
+        assert_eq!(disruption("null").valid_until_local(), None);
+    }
+
+    #[test]
+    fn diversion_dates_are_localised() {
+        let diversions: Diversions = serde_json::from_str(
+            r#"{"diversions": [{
+                "ref": "v1", "diversionId": "123", "operatorId": "LB", "refService": "26",
+                "startStopId": "1", "startStopName": "A", "startDate": "2018-03-24T12:00:00Z",
+                "endStopId": "2", "endStopName": "B", "endDate": "2018-03-26T12:00:00Z",
+                "days": "1111111", "length": 1200, "timeShift": 5,
+                "cancelledBusStops": [], "temporaryBusStops": []
+            }]}"#,
+        )
+        .unwrap();
+        let diversion = &diversions.diversions[0];
+
+        assert_eq!(diversion.start_date_local().to_rfc3339(), "2018-03-24T12:00:00+00:00");
+        assert_eq!(diversion.end_date_local().to_rfc3339(), "2018-03-26T13:00:00+01:00");
+        assert_eq!(diversion.end_date_local(), diversion.end_date);
+    }
+}
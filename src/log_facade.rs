@@ -0,0 +1,114 @@
+//! `log` Facade Integration
+//!
+//! With the `log` feature enabled, the default logger forwards each record to the `log` facade,
+//! so the crate's output reaches whichever `log` implementation the application has installed
+//! (e.g. `env_logger`). Requires the `log` feature.
+
+use std::fmt::{self, Write};
+
+use slog::{Drain, Key, Level, Never, OwnedKVList, Record, Serializer, KV};
+
+/// A drain forwarding slog records to the `log` facade.
+///
+/// Each record is logged with its module as the target, and its key-value pairs appended to
+/// the message as `key=value`.
+pub struct LogDrain;
+
+impl Drain for LogDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+        let level = match record.level() {
+            Level::Critical | Level::Error => log::Level::Error,
+            Level::Warning => log::Level::Warn,
+            Level::Info => log::Level::Info,
+            Level::Debug => log::Level::Debug,
+            Level::Trace => log::Level::Trace,
+        };
+        let logger = log::logger();
+        let metadata = log::Metadata::builder()
+            .level(level)
+            .target(record.module())
+            .build();
+        if !logger.enabled(&metadata) {
+            return Ok(());
+        }
+
+        let mut pairs = Pairs(String::new());
+        // Formatting into a `String` cannot fail.
+        let _ = record.kv().serialize(record, &mut pairs);
+        let _ = values.serialize(record, &mut pairs);
+
+        logger.log(
+            &log::Record::builder()
+                .metadata(metadata)
+                .args(format_args!("{}{}", record.msg(), pairs.0))
+                .module_path_static(Some(record.module()))
+                .file_static(Some(record.file()))
+                .line(Some(record.line()))
+                .build(),
+        );
+        Ok(())
+    }
+}
+
+/// Key-value pairs, formatted as ` key=value` for each pair.
+struct Pairs(String);
+
+impl Serializer for Pairs {
+    fn emit_arguments(&mut self, key: Key, value: &fmt::Arguments) -> slog::Result {
+        write!(self.0, " {}={}", key, value)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use slog::Logger;
+
+    use super::*;
+
+    /// Records logged through the `log` facade, as `level target: message`.
+    static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Debug
+        }
+
+        fn log(&self, record: &log::Record) {
+            RECORDS.lock().unwrap().push(format!(
+                "{} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn records_are_forwarded_with_their_pairs() {
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let logger = Logger::root(LogDrain, o!("operator" => "LB"));
+        warn!(logger, "Request failed"; "status" => 503);
+        debug!(logger, "Getting services");
+        trace!(logger, "Not enabled");
+
+        assert_eq!(
+            *RECORDS.lock().unwrap(),
+            vec![
+                "WARN my_bus_tracker::log_facade::tests: Request failed status=503 operator=LB",
+                "DEBUG my_bus_tracker::log_facade::tests: Getting services operator=LB",
+            ]
+        );
+    }
+}
@@ -1,31 +1,129 @@
 //! Models representing data types returned by the My Bus Tracker API
-#![allow(similar_names)]
+//!
+//! The web service locates stops and route points by OSGB36 National Grid easting and
+//! northing. Their `wgs84` methods convert these to WGS84 latitude and longitude, for use with
+//! GPS coordinates and web maps.
+#![allow(clippy::similar_names)]
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 use serde::de::Error as SerdeError;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use chrono::prelude::*;
 use std::ops::Deref;
 
-#[derive(Clone, Debug)]
+use super::{geo, html, MyBusTrackerError};
+pub use super::geo::haversine;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Timetable {
-    pub stop_id: String,
+    pub stop_id: StopId,
     pub service_reference: String,
     pub destination_reference: String,
     pub operator_id: Operator,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Timetable {
+    /// Create a new timetable request.
+    ///
+    /// Fails if the stop ID, service reference or destination reference is empty.
+    pub fn new(
+        stop_id: StopId,
+        service_reference: &str,
+        destination_reference: &str,
+        operator_id: Operator,
+    ) -> Result<Self, MyBusTrackerError> {
+        let fields = [
+            ("stop_id", stop_id.as_str()),
+            ("service_reference", service_reference),
+            ("destination_reference", destination_reference),
+        ];
+        if let Some(&(field, _)) = fields.iter().find(|&&(_, value)| value.trim().is_empty()) {
+            return Err(MyBusTrackerError::InvalidTimetable { field });
+        }
+
+        Ok(Self {
+            stop_id,
+            service_reference: service_reference.to_owned(),
+            destination_reference: destination_reference.to_owned(),
+            operator_id,
+        })
+    }
+
+    /// Create a new timetable request for every service calling at a stop, without needing
+    /// their service or destination references.
+    ///
+    /// The service and destination references are left empty, and omitted from the request.
+    /// Fails if the stop ID is empty.
+    pub fn for_stop(stop_id: StopId, operator_id: Operator) -> Result<Self, MyBusTrackerError> {
+        if stop_id.as_str().trim().is_empty() {
+            return Err(MyBusTrackerError::InvalidTimetable { field: "stop_id" });
+        }
+
+        Ok(Self {
+            stop_id,
+            service_reference: String::new(),
+            destination_reference: String::new(),
+            operator_id,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BusTimes {
     pub bus_times: Vec<BusTime>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl BusTimes {
+    /// Every departure, alongside the service and stop it belongs to.
+    pub fn all_times(&self) -> impl Iterator<Item = (&BusTime, &TimeData)> {
+        self.bus_times
+            .iter()
+            .flat_map(|bus_time| bus_time.times.iter().map(move |time| (bus_time, time)))
+    }
+
+    /// The services, ordered by their soonest departure.
+    ///
+    /// Services without any departures are ordered last.
+    pub fn sorted_by_arrival(&self) -> Vec<&BusTime> {
+        let mut bus_times: Vec<&BusTime> = self.bus_times.iter().collect();
+        bus_times.sort_by_key(|bus_time| {
+            bus_time
+                .times
+                .iter()
+                .map(|time| time.minutes)
+                .min()
+                .map_or(u16::MAX, u16::from)
+        });
+        bus_times
+    }
+
+    /// The services with the given mnemonic, e.g. `"22"`.
+    pub fn for_service(&self, mnemonic: &str) -> Vec<&BusTime> {
+        self.bus_times
+            .iter()
+            .filter(|bus_time| bus_time.service_mnemonic == mnemonic)
+            .collect()
+    }
+
+    /// The departures based on the realtime position of the bus, alongside the service and stop
+    /// they belong to.
+    ///
+    /// See `Reliability::is_realtime`.
+    pub fn realtime_only(&self) -> Vec<(&BusTime, &TimeData)> {
+        self.all_times()
+            .filter(|&(_, time)| time.reliability.is_realtime())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BusTime {
     pub operator_id: Operator,
-    pub stop_id: String,
+    pub stop_id: StopId,
     pub stop_name: String,
     #[serde(rename = "refService")]
     pub service_reference: String,
@@ -39,15 +137,44 @@ pub struct BusTime {
     pub destination_name: Option<String>,
     #[serde(rename = "timeDatas")]
     pub times: Vec<TimeData>,
+    #[serde(default)]
     pub global_disruption: bool,
+    #[serde(default)]
     pub service_disruption: bool,
+    #[serde(default)]
     pub bus_stop_disruption: bool,
+    #[serde(default)]
     pub service_diversion: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// A one-line summary of the soonest departure, e.g.
+/// `Service 26 to Seton Sands — 3 min (real-time)`.
+///
+/// The destination falls back to the departure's terminus, and is omitted if neither is known.
+impl Display for BusTime {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let soonest = self.times.iter().min_by_key(|time| time.minutes);
+        let destination = self
+            .destination_name
+            .as_ref()
+            .or_else(|| soonest.map(|time| &time.terminus))
+            .filter(|destination| !destination.is_empty());
+
+        write!(f, "Service {}", self.service_mnemonic)?;
+        if let Some(destination) = destination {
+            write!(f, " to {}", destination)?;
+        }
+        match soonest {
+            Some(time) => write!(f, " — {} min ({})", time.minutes, time.reliability.summary()),
+            None => write!(f, " — no departures"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TimeData {
+    /// Day of departure, as an offset in days from the day of the request (0 being today).
     pub day: u8,
     pub time: String,
     pub minutes: u8,
@@ -59,7 +186,49 @@ pub struct TimeData {
     pub bus_id: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl TimeData {
+    /// The departure time, if given as a clock time (`HH:MM` or `HH:MM:SS`).
+    ///
+    /// Returns `None` if the time cannot be parsed, e.g. if it is given relative to now.
+    pub fn parsed_time(&self) -> Option<NaiveTime> {
+        parse_naive_time(&self.time)
+    }
+
+    /// The estimated departure time, given the time at which the response was fetched.
+    ///
+    /// `minutes` counts down from the time of the request, so this naturally accounts for
+    /// departures after midnight, without reference to `day`.
+    pub fn arrival_at(&self, reference: DateTime<Utc>) -> DateTime<Utc> {
+        reference + chrono::Duration::minutes(i64::from(self.minutes))
+    }
+
+    /// The date of departure, given the date on which the response was fetched.
+    #[allow(deprecated)]
+    pub fn date(&self, reference: Date<Utc>) -> Date<Utc> {
+        reference + chrono::Duration::days(i64::from(self.day))
+    }
+
+    /// Whether the departure is on the day of the request (`day` is 0).
+    pub fn is_today(&self) -> bool {
+        self.day == 0
+    }
+
+    /// Whether the departure is on the day after the request (`day` is 1), e.g. a late-night
+    /// departure after midnight.
+    pub fn is_tomorrow(&self) -> bool {
+        self.day == 1
+    }
+
+    /// The date of service, i.e. the date on which the response was fetched (`reference`)
+    /// offset by `day`. This is the same as `date`, named alongside `is_today` and
+    /// `is_tomorrow`.
+    #[allow(deprecated)]
+    pub fn service_date(&self, reference: Date<Utc>) -> Date<Utc> {
+        self.date(reference)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Reliability {
     #[serde(rename = "B")]
     Delayed,
@@ -80,22 +249,168 @@ pub enum Reliability {
     #[serde(rename = "V")]
     Diverted,
 }
-#[derive(Clone, Debug, Deserialize)]
+
+impl Reliability {
+    /// Whether the time is based on the realtime position of the bus.
+    pub fn is_realtime(&self) -> bool {
+        matches!(
+            *self,
+            Reliability::RealTimeNotLowFloorEquipped | Reliability::RealTimeLowFloorEquipped
+        )
+    }
+
+    /// Whether the bus is low-floor equipped, if known.
+    ///
+    /// Only realtime reliabilities carry this information.
+    pub fn is_low_floor(&self) -> Option<bool> {
+        match *self {
+            Reliability::RealTimeNotLowFloorEquipped => Some(false),
+            Reliability::RealTimeLowFloorEquipped => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Whether the time is estimated from the timetable, rather than the position of the bus.
+    pub fn is_estimated(&self) -> bool {
+        matches!(*self, Reliability::Estimated)
+    }
+
+    /// A short, human-readable description of the reliability.
+    fn summary(&self) -> &'static str {
+        match *self {
+            Reliability::RealTimeNotLowFloorEquipped | Reliability::RealTimeLowFloorEquipped => {
+                "real-time"
+            }
+            Reliability::Estimated => "timetabled",
+            Reliability::Delayed => "delayed",
+            Reliability::Diverted => "diverted",
+            Reliability::Delocated
+            | Reliability::Immobilized
+            | Reliability::Neutralized
+            | Reliability::RadioFault => "unreliable",
+        }
+    }
+}
+
+/// The role of a stop in a journey.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum StopType {
-    #[serde(rename = "D")]
     Terminus,
-    #[serde(rename = "N")]
     Normal,
-    #[serde(rename = "P")]
     PartRoute,
-    #[serde(rename = "R")]
     Reference,
+    /// A stop type not otherwise known to this crate, holding its code.
+    Other(String),
+}
+
+impl Display for StopType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let printable = match *self {
+            StopType::Terminus => "D",
+            StopType::Normal => "N",
+            StopType::PartRoute => "P",
+            StopType::Reference => "R",
+            StopType::Other(ref code) => code,
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for StopType {
+    type Err = ParseError;
+
+    /// Parse a stop type code. Unknown, but non-empty, codes are parsed as `StopType::Other`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "D" => Ok(StopType::Terminus),
+            "N" => Ok(StopType::Normal),
+            "P" => Ok(StopType::PartRoute),
+            "R" => Ok(StopType::Reference),
+            code if code.trim().is_empty() => Err(ParseError {
+                kind: "StopType",
+                value: code.to_owned(),
+            }),
+            code => Ok(StopType::Other(code.to_owned())),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StopType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for StopType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Identifier of a bus stop.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct StopId(String);
+
+impl StopId {
+    /// The stop identifier, as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for StopId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<String> for StopId {
+    fn from(stop_id: String) -> Self {
+        StopId(stop_id)
+    }
+}
+
+impl<'a> From<&'a str> for StopId {
+    fn from(stop_id: &'a str) -> Self {
+        StopId(stop_id.to_owned())
+    }
+}
+
+/// Error returned when a model type cannot be parsed from a string.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid {kind}: {value:?}")]
+pub struct ParseError {
+    kind: &'static str,
+    value: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Operator {
     LothianBuses,
+    /// Wildcard, for requesting data of every operator at once.
     AllOperators,
+    /// An operator not otherwise known to this crate, holding its operator code.
+    Other(String),
+}
+
+/// The concrete operators known to this crate.
+static KNOWN_OPERATORS: [Operator; 1] = [Operator::LothianBuses];
+
+impl Operator {
+    /// The concrete operators known to this crate.
+    ///
+    /// Neither the `AllOperators` wildcard nor `Other` operators are included.
+    pub fn all() -> &'static [Operator] {
+        &KNOWN_OPERATORS
+    }
 }
 
 impl Display for Operator {
@@ -103,34 +418,61 @@ impl Display for Operator {
         let printable = match *self {
             Operator::LothianBuses => "LB",
             Operator::AllOperators => "0",
+            Operator::Other(ref code) => code,
         };
         write!(f, "{}", printable)
     }
 }
 
+impl FromStr for Operator {
+    type Err = ParseError;
+
+    /// Parse an operator code. Unknown, but non-empty, codes are parsed as `Operator::Other`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "LB" => Ok(Operator::LothianBuses),
+            "0" | "ALL" => Ok(Operator::AllOperators),
+            code if code.trim().is_empty() => Err(ParseError {
+                kind: "Operator",
+                value: code.to_owned(),
+            }),
+            code => Ok(Operator::Other(code.to_owned())),
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Operator {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let s: &str = Deserialize::deserialize(deserializer)?;
-        match s {
-            "LB" => Ok(Operator::LothianBuses),
-            "0" | "ALL" => Ok(Operator::AllOperators),
-            e => Err(D::Error::custom(format!("Unknown Operator: {}", e))),
-        }
+        let s: String = Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
     }
 }
 
-#[derive(Clone, Debug)]
+impl Serialize for Operator {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum JourneyIdentifier {
     JourneyId(String),
     BusId(String),
 }
 
-#[derive(Clone, Debug)]
+/// How many stops of a journey `get_journey_times` should return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum JourneyTimeMode {
+    /// Every stop of the journey.
     All,
+    /// Only the stops up to the next reference stop (`StopType::Reference`), which may be
+    /// extracted with `JourneyTimes::next_reference`.
     NextReference,
 }
 
@@ -144,13 +486,28 @@ impl Display for JourneyTimeMode {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JourneyTimes {
     pub journey_times: Vec<JourneyTime>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl JourneyTimes {
+    /// The soonest reference stop (`StopType::Reference`) the bus has not yet passed, across
+    /// all journeys.
+    ///
+    /// This is the stop requested with `JourneyTimeMode::NextReference`, but may be used with
+    /// either mode. Returns `None` if there is no such stop.
+    pub fn next_reference(&self) -> Option<&JourneyTimeData> {
+        self.journey_times
+            .iter()
+            .flat_map(|journey_time| journey_time.journey_times.iter())
+            .filter(|time| time.stop_type == StopType::Reference && !time.has_departed())
+            .min_by_key(|time| time.minutes)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JourneyTime {
     pub journey_id: String,
@@ -168,40 +525,94 @@ pub struct JourneyTime {
     pub destination_name: String,
     #[serde(rename = "journeyTimeDatas")]
     pub journey_times: Vec<JourneyTimeData>,
+    #[serde(default)]
     pub global_disruption: bool,
+    #[serde(default)]
     pub service_disruption: bool,
+    #[serde(default)]
     pub service_diversion: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JourneyTimeData {
     pub order: u32,
-    pub stop_id: String,
+    pub stop_id: StopId,
     pub stop_name: String,
-    pub day: u32,           //TODO - Date
-    pub time: NaiveTimeExt, // TODO - Date
+    /// Day of arrival, as an offset in days from the day of the request (0 being today).
+    pub day: u32,
+    pub time: NaiveTimeExt,
+    /// Minutes until the bus arrives at the stop, from the time of the request. Negative for
+    /// stops the bus has already passed.
     pub minutes: i32,
     pub reliability: Reliability,
     #[serde(rename = "type")]
-    pub stop_type: String,
-    #[serde(rename = "busStopDisruption")]
+    pub stop_type: StopType,
+    #[serde(rename = "busStopDisruption", default)]
     pub disruption: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl JourneyTimeData {
+    /// The date of arrival, given the date on which the response was fetched.
+    #[allow(deprecated)]
+    pub fn date(&self, reference: Date<Utc>) -> Date<Utc> {
+        reference + chrono::Duration::days(i64::from(self.day))
+    }
+
+    /// Whether the bus had already passed the stop at the time of the request.
+    pub fn has_departed(&self) -> bool {
+        self.minutes < 0
+    }
+
+    /// Minutes until the bus arrives at the stop, or `None` if it has already passed.
+    pub fn minutes_until(&self) -> Option<u32> {
+        u32::try_from(self.minutes).ok()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TopoId {
     pub topo_id: String,
     pub operator_id: Operator,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// A snapshot of an operator's topological data, as assembled by
+/// `TopologicalServices::get_topology`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Topology {
+    /// The topology ID which was current when the snapshot was taken.
+    pub id: TopoId,
+    pub services: Services,
+    pub destinations: Destinations,
+    pub bus_stops: BusStops,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Services {
     pub services: Vec<Service>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Services {
+    /// The service with the given reference, if any.
+    pub fn by_reference(&self, reference: &str) -> Option<&Service> {
+        self.services
+            .iter()
+            .find(|service| service.reference == reference)
+    }
+
+    /// The services with the given references, e.g. those of a `BusStop`.
+    ///
+    /// Unknown references are skipped.
+    pub fn resolve<'a>(&'a self, references: &[String]) -> Vec<&'a Service> {
+        references
+            .iter()
+            .filter_map(|reference| self.by_reference(reference))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Service {
     #[serde(rename = "ref")]
     pub reference: String,
@@ -214,7 +625,20 @@ pub struct Service {
     pub destinations: Vec<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// Everything needed to describe a single stop, as assembled by
+/// `MyBusTracker::get_stop_overview`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StopOverview {
+    pub stop: BusStop,
+    /// The services calling at the stop.
+    pub services: Vec<Service>,
+    /// The next departures from the stop, of every service.
+    pub times: BusTimes,
+    /// The disruptions affecting the stop.
+    pub disruptions: Disruptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ServicePoints {
     #[serde(rename = "ref")]
@@ -223,23 +647,84 @@ pub struct ServicePoints {
     pub service_points: Vec<ServicePoint>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl ServicePoints {
+    /// The WGS84 `(latitude, longitude)` points of the route, in order.
+    ///
+    /// Where several points share the same order, only the first is kept.
+    pub fn to_polyline(&self) -> Vec<(f64, f64)> {
+        geo::polyline(
+            self.service_points
+                .iter()
+                .map(|point| (point.order, point.wgs84())),
+        )
+    }
+
+    /// The length of the route, in metres.
+    ///
+    /// This is the sum of the great-circle distances between consecutive points of
+    /// `to_polyline`, rather than the reported `chainage`, so it is unaffected by the order in
+    /// which points are listed. Routes with fewer than two points have zero length.
+    pub fn route_length_metres(&self) -> f64 {
+        self.to_polyline()
+            .windows(2)
+            .map(|pair| haversine(pair[0], pair[1]))
+            .sum()
+    }
+
+    /// The route as a GeoJSON `LineString` feature, with the service reference and operator as
+    /// properties.
+    pub fn to_geojson(&self) -> serde_json::Value {
+        geo::line_string_feature(
+            &self.to_polyline(),
+            serde_json::json!({
+                "service_reference": self.service_reference,
+                "operator_id": self.operator_id,
+            }),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ServicePoint {
     pub chainage: u32,
     pub order: u32,
     #[serde(rename = "x")]
-    pub latitude: f32,
+    pub easting: f64,
     #[serde(rename = "y")]
-    pub longitude: f32,
+    pub northing: f64,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl ServicePoint {
+    /// The WGS84 `(latitude, longitude)` of this point, in degrees.
+    pub fn wgs84(&self) -> (f64, f64) {
+        geo::osgb36_to_wgs84(self.easting, self.northing)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Destinations {
     #[serde(rename = "dests")]
     pub destinations: Vec<Destination>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Destinations {
+    /// The destinations of the service with the given reference.
+    pub fn for_service<'a>(&'a self, service_reference: &str) -> Vec<&'a Destination> {
+        self.destinations
+            .iter()
+            .filter(|destination| destination.service == service_reference)
+            .collect()
+    }
+
+    /// The destinations, partitioned into `(inbound, outbound)` by their direction.
+    pub fn by_direction(&self) -> (Vec<&Destination>, Vec<&Destination>) {
+        self.destinations
+            .iter()
+            .partition(|destination| destination.direction == Direction::Inbound)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Destination {
     #[serde(rename = "ref")]
@@ -250,7 +735,7 @@ pub struct Destination {
     pub service: String,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Direction {
     #[serde(rename = "A")]
     Inbound,
@@ -258,22 +743,132 @@ pub enum Direction {
     Outbound,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// Displays the wire code of the direction - `A` for inbound and `R` for outbound.
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let printable = match *self {
+            Direction::Inbound => "A",
+            Direction::Outbound => "R",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ParseError;
+
+    /// Parse the wire code of a direction.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A" => Ok(Direction::Inbound),
+            "R" => Ok(Direction::Outbound),
+            e => Err(ParseError {
+                kind: "Direction",
+                value: e.to_owned(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BusStops {
     pub bus_stops: Vec<BusStop>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl BusStops {
+    /// The stops within the given WGS84 bounding box, in degrees.
+    ///
+    /// Stops exactly on the boundary are included.
+    pub fn within_bbox(
+        &self,
+        min_lat: f64,
+        min_lon: f64,
+        max_lat: f64,
+        max_lon: f64,
+    ) -> Vec<&BusStop> {
+        self.bus_stops
+            .iter()
+            .filter(|bus_stop| {
+                let (lat, lon) = bus_stop.wgs84();
+                lat >= min_lat && lat <= max_lat && lon >= min_lon && lon <= max_lon
+            })
+            .collect()
+    }
+
+    /// Up to `limit` stops closest to the given WGS84 latitude and longitude (in degrees),
+    /// nearest first, alongside their distance in metres.
+    pub fn nearest(&self, latitude: f64, longitude: f64, limit: usize) -> Vec<(&BusStop, f64)> {
+        let mut stops: Vec<(&BusStop, f64)> = self
+            .bus_stops
+            .iter()
+            .map(|bus_stop| (bus_stop, bus_stop.distance_to(latitude, longitude)))
+            .collect();
+        stops.sort_by(|a, b| a.1.total_cmp(&b.1));
+        stops.truncate(limit);
+        stops
+    }
+
+    /// The stops as a GeoJSON `FeatureCollection` of `Point` features.
+    ///
+    /// Each feature has the stop's name, ID, orientation and services as properties.
+    pub fn to_geojson(&self) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self
+            .bus_stops
+            .iter()
+            .map(|bus_stop| {
+                geo::point_feature(
+                    bus_stop.wgs84(),
+                    serde_json::json!({
+                        "name": bus_stop.name,
+                        "stop_id": bus_stop.stop_id,
+                        "orientation": bus_stop.orientation,
+                        "services": bus_stop.services,
+                    }),
+                )
+            })
+            .collect();
+        serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+    }
+
+    /// The stops whose names contain the given query, ignoring case and surrounding whitespace.
+    ///
+    /// An empty query matches no stops.
+    pub fn search(&self, query: &str) -> Vec<&BusStop> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.bus_stops
+            .iter()
+            .filter(|bus_stop| bus_stop.name.to_lowercase().contains(&query))
+            .collect()
+    }
+
+    /// The stops of the given operator. `Operator::AllOperators` matches every stop.
+    pub fn by_operator(&self, operator: &Operator) -> Vec<&BusStop> {
+        self.bus_stops
+            .iter()
+            .filter(|bus_stop| {
+                *operator == Operator::AllOperators || bus_stop.operator_id == *operator
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BusStop {
     pub operator_id: Operator,
-    pub stop_id: String,
+    pub stop_id: StopId,
     pub name: String,
     #[serde(rename = "x")]
-    pub latitude: f32,
+    pub easting: f64,
     #[serde(rename = "y")]
-    pub longitude: f32,
+    pub northing: f64,
     #[serde(rename = "cap")]
     pub orientation: u16,
     pub services: Vec<String>,
@@ -281,7 +876,81 @@ pub struct BusStop {
     pub destinations: Vec<String>,
 }
 
-#[derive(Clone, Debug)]
+impl BusStop {
+    /// The WGS84 `(latitude, longitude)` of this stop, in degrees.
+    pub fn wgs84(&self) -> (f64, f64) {
+        geo::osgb36_to_wgs84(self.easting, self.northing)
+    }
+
+    /// The great-circle distance, in metres, from this stop to the given WGS84 latitude and
+    /// longitude (in degrees).
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> f64 {
+        haversine(self.wgs84(), (latitude, longitude))
+    }
+
+    /// The nearest of the eight principal compass points to the direction the stop faces.
+    ///
+    /// `orientation` is a bearing in degrees clockwise from north. Bearings of 360 degrees or
+    /// more wrap around, so e.g. both 0 and 360 face north.
+    pub fn compass_direction(&self) -> CompassPoint {
+        CompassPoint::from_bearing(self.orientation)
+    }
+}
+
+/// One of the eight principal points of the compass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum CompassPoint {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl CompassPoint {
+    /// The compass points, clockwise from north.
+    const CLOCKWISE: [CompassPoint; 8] = [
+        CompassPoint::North,
+        CompassPoint::NorthEast,
+        CompassPoint::East,
+        CompassPoint::SouthEast,
+        CompassPoint::South,
+        CompassPoint::SouthWest,
+        CompassPoint::West,
+        CompassPoint::NorthWest,
+    ];
+
+    /// The nearest compass point to a bearing, in degrees clockwise from north.
+    ///
+    /// Each point covers the 45 degrees centred on it, e.g. north covers bearings from 337.5
+    /// up to (but excluding) 22.5 degrees.
+    pub fn from_bearing(bearing: u16) -> Self {
+        // Doubled, to compare against the half-degree bucket boundaries in integers.
+        let doubled = u32::from(bearing % 360) * 2;
+        Self::CLOCKWISE[((doubled + 45) / 90 % 8) as usize]
+    }
+}
+
+impl Display for CompassPoint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let printable = match *self {
+            CompassPoint::North => "north",
+            CompassPoint::NorthEast => "north-east",
+            CompassPoint::East => "east",
+            CompassPoint::SouthEast => "south-east",
+            CompassPoint::South => "south",
+            CompassPoint::SouthWest => "south-west",
+            CompassPoint::West => "west",
+            CompassPoint::NorthWest => "north-west",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DisruptionType {
     All,
     Network,
@@ -305,6 +974,21 @@ impl<'de> Deserialize<'de> for DisruptionType {
     }
 }
 
+impl Serialize for DisruptionType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let i: u8 = match *self {
+            DisruptionType::All => 0,
+            DisruptionType::Network => 1,
+            DisruptionType::Service => 2,
+            DisruptionType::BusStop => 3,
+        };
+        serializer.serialize_u8(i)
+    }
+}
+
 impl Display for DisruptionType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let printable = match *self {
@@ -317,12 +1001,79 @@ impl Display for DisruptionType {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Disruptions {
     pub disruptions: Vec<Disruption>,
 }
 
-#[derive(Clone, Debug)]
+impl Disruptions {
+    /// The disruptions which are active at the given time.
+    pub fn active(&self, now: DateTime<Utc>) -> Vec<&Disruption> {
+        self.disruptions
+            .iter()
+            .filter(|disruption| disruption.is_active(now))
+            .collect()
+    }
+
+    /// The disruptions of at least the given severity.
+    pub fn at_least(&self, level: DisruptionLevel) -> Vec<&Disruption> {
+        self.disruptions
+            .iter()
+            .filter(|disruption| disruption.level >= level)
+            .collect()
+    }
+
+    /// The changes from a previous set of disruptions to this one, matching disruptions by ID.
+    ///
+    /// Disruptions which are new, or whose details differ, are reported in the order they
+    /// appear in this set, followed by those which are no longer present.
+    pub fn changes_since(&self, previous: &Disruptions) -> Vec<DisruptionEvent> {
+        let previous_by_id: HashMap<&str, &Disruption> = previous
+            .disruptions
+            .iter()
+            .map(|disruption| (disruption.id.as_str(), disruption))
+            .collect();
+        let current_ids: HashSet<&str> = self.disruptions
+            .iter()
+            .map(|disruption| disruption.id.as_str())
+            .collect();
+
+        let mut events: Vec<DisruptionEvent> = self.disruptions
+            .iter()
+            .filter_map(|disruption| match previous_by_id.get(disruption.id.as_str()) {
+                None => Some(DisruptionEvent::Added(disruption.clone())),
+                Some(&old) if old != disruption => {
+                    Some(DisruptionEvent::Changed(disruption.clone()))
+                }
+                Some(_) => None,
+            })
+            .collect();
+        events.extend(
+            previous
+                .disruptions
+                .iter()
+                .filter(|disruption| !current_ids.contains(disruption.id.as_str()))
+                .map(|disruption| DisruptionEvent::Removed(disruption.id.clone())),
+        );
+        events
+    }
+}
+
+/// A change to the set of ongoing disruptions, between two successive requests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DisruptionEvent {
+    /// A disruption which was not previously present.
+    Added(Disruption),
+    /// A disruption, identified by its ID, which is no longer present.
+    Removed(String),
+    /// A previously present disruption whose details have changed.
+    Changed(Disruption),
+}
+
+/// Severity of a disruption.
+///
+/// Levels are ordered by severity, from `Informative` to `Major`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DisruptionLevel {
     Informative,
     Minor,
@@ -344,6 +1095,20 @@ impl<'de> Deserialize<'de> for DisruptionLevel {
     }
 }
 
+impl Serialize for DisruptionLevel {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let i: u8 = match *self {
+            DisruptionLevel::Informative => 1,
+            DisruptionLevel::Minor => 2,
+            DisruptionLevel::Major => 3,
+        };
+        serializer.serialize_u8(i)
+    }
+}
+
 impl Display for DisruptionLevel {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let printable = match *self {
@@ -355,7 +1120,7 @@ impl Display for DisruptionLevel {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Disruption {
     pub id: String,
@@ -368,12 +1133,36 @@ pub struct Disruption {
     pub message: String,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Disruption {
+    /// Whether the disruption is active at the given time.
+    ///
+    /// Disruptions without a `valid_until` time are open-ended, and so always active.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        match self.valid_until {
+            Some(valid_until) => valid_until > now,
+            None => true,
+        }
+    }
+
+    /// Whether the given service reference or stop ID is among the disruption's targets.
+    pub fn affects(&self, target: &str) -> bool {
+        self.targets.iter().any(|t| t == target)
+    }
+
+    /// The message as plain text, with HTML markup removed and character references decoded.
+    ///
+    /// Line breaks (`<br>`), and the ends of paragraphs, become newlines.
+    pub fn plain_message(&self) -> String {
+        html::to_plain_text(&self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Diversions {
     pub diversions: Vec<Diversion>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Diversion {
     #[serde(rename = "ref")]
@@ -382,10 +1171,10 @@ pub struct Diversion {
     pub operator_id: Operator,
     #[serde(rename = "refService")]
     pub service_reference: String,
-    pub start_stop_id: String,
+    pub start_stop_id: StopId,
     pub start_stop_name: String,
     pub start_date: DateTime<Utc>,
-    pub end_stop_id: String,
+    pub end_stop_id: StopId,
     pub end_stop_name: String,
     pub end_date: DateTime<Utc>,
     pub days: String,
@@ -395,46 +1184,138 @@ pub struct Diversion {
     pub temporary_bus_stops: Vec<TemporaryBusStop>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Diversion {
+    /// The days of the week on which the diversion applies, if `days` can be parsed.
+    pub fn days_of_week(&self) -> Option<DaysOfWeek> {
+        self.days.parse().ok()
+    }
+}
+
+/// A set of days of the week.
+///
+/// Parsed from a seven character mask, with one character per day from Monday to Sunday, e.g.
+/// `1111100` or `MTWTF__` for weekdays only. Each day is included unless its character is `0`,
+/// `_`, `-` or a space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DaysOfWeek(u8);
+
+impl DaysOfWeek {
+    /// Whether the given day is included in the set.
+    pub fn applies_on(&self, weekday: Weekday) -> bool {
+        self.0 & (1 << weekday.num_days_from_monday()) != 0
+    }
+}
+
+impl FromStr for DaysOfWeek {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.chars().count() != 7 {
+            return Err(ParseError {
+                kind: "DaysOfWeek",
+                value: s.to_owned(),
+            });
+        }
+        let mask = s
+            .chars()
+            .enumerate()
+            .filter(|&(_, c)| !matches!(c, '0' | '_' | '-' | ' '))
+            .fold(0, |mask, (i, _)| mask | (1 << i));
+        Ok(DaysOfWeek(mask))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CancelledBusStop {
-    pub stop_id: String,
+    pub stop_id: StopId,
     pub stop_name: String,
-    pub replaced_stop_id: String,
+    pub replaced_stop_id: StopId,
     pub replaced_stop_name: String,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TemporaryBusStop {
-    pub stop_id: String,
+    pub stop_id: StopId,
     pub stop_name: String,
     #[serde(rename = "num")]
     pub stop_number: u32,
     #[serde(rename = "type")]
-    pub stop_type: String,
+    pub stop_type: StopType,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DiversionPoints {
-    //    pub diversion_id: String,
+    /// The requested diversion ID. Filled in by `get_diversion_points` if not in the response.
+    #[serde(default)]
+    pub diversion_id: Option<String>,
     //    pub operator_id: Operator,
     pub diversion_points: Vec<DiversionPoint>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl DiversionPoints {
+    /// The diversion as a GeoJSON `LineString` feature, with the diversion ID as a property.
+    ///
+    /// Points are sorted by order, and where several share the same order, only the first is
+    /// kept.
+    pub fn to_geojson(&self) -> serde_json::Value {
+        let points = geo::polyline(
+            self.diversion_points
+                .iter()
+                .map(|point| (point.order, point.wgs84())),
+        );
+        geo::line_string_feature(
+            &points,
+            serde_json::json!({ "diversion_id": self.diversion_id }),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct DiversionPoint {
     pub order: u32,
     #[serde(rename = "x")]
-    pub latitude: f32,
+    pub easting: f64,
     #[serde(rename = "y")]
-    pub longitude: f32,
+    pub northing: f64,
 }
 
-#[derive(Clone, Debug)]
+impl DiversionPoint {
+    /// The WGS84 `(latitude, longitude)` of this point, in degrees.
+    pub fn wgs84(&self) -> (f64, f64) {
+        geo::osgb36_to_wgs84(self.easting, self.northing)
+    }
+}
+
+/// A time of day, deserialized from `HH:MM:SS` or `HH:MM` form.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct NaiveTimeExt(NaiveTime);
 
+/// Formats accepted when parsing a time of day, in the order they are tried.
+const NAIVE_TIME_FORMATS: [&str; 2] = ["%H:%M:%S", "%H:%M"];
+
+/// Parse a time of day in any of the `NAIVE_TIME_FORMATS`.
+fn parse_naive_time(time: &str) -> Option<NaiveTime> {
+    NAIVE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+}
+
+impl NaiveTimeExt {
+    /// The wrapped time.
+    pub fn into_inner(self) -> NaiveTime {
+        self.0
+    }
+}
+
+impl From<NaiveTime> for NaiveTimeExt {
+    fn from(time: NaiveTime) -> Self {
+        NaiveTimeExt(time)
+    }
+}
+
 impl Deref for NaiveTimeExt {
     type Target = NaiveTime;
     fn deref(&self) -> &Self::Target {
@@ -448,8 +1329,793 @@ impl<'de> Deserialize<'de> for NaiveTimeExt {
         D: Deserializer<'de>,
     {
         let time_string: String = Deserialize::deserialize(deserializer)?;
-        NaiveTime::parse_from_str(&time_string, "%H:%M")
-            .map_err(D::Error::custom)
+        parse_naive_time(&time_string)
             .map(NaiveTimeExt)
+            .ok_or_else(|| D::Error::custom(format!("Invalid time: {:?}", time_string)))
+    }
+}
+
+impl Serialize for NaiveTimeExt {
+    /// Serialize as `HH:MM`, or `HH:MM:SS` if the seconds are non-zero.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let format = if self.0.second() == 0 { "%H:%M" } else { "%H:%M:%S" };
+        serializer.collect_str(&self.0.format(format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use std::collections::HashSet;
+
+    const BUS_TIMES: &str = r#"{"busTimes": [{
+        "operatorId": "LB", "stopId": "36232626", "stopName": "Princes Street",
+        "refService": "26", "mnemoService": "26", "nameService": "Clerwood - Seton Sands",
+        "refDest": "26A", "nameDest": "Seton Sands",
+        "timeDatas": [
+            {"day": 0, "time": "14:35", "minutes": 3, "reliability": "H", "type": "N",
+             "terminus": "Seton Sands", "journeyId": "1234", "busId": "567"},
+            {"day": 1, "time": "00:10", "minutes": 98, "reliability": "T", "type": "D",
+             "terminus": "Tower Place", "journeyId": "1235", "busId": null}
+        ],
+        "globalDisruption": false, "serviceDisruption": true,
+        "busStopDisruption": false, "serviceDiversion": false
+    }]}"#;
+
+    const JOURNEY_TIMES: &str = r#"{"journeyTimes": [{
+        "journeyId": "1234", "busId": "567", "operatorId": "LB",
+        "refService": "26", "mnemoService": "26", "nameService": "Clerwood - Seton Sands",
+        "refDest": "26A", "nameDest": "Seton Sands",
+        "journeyTimeDatas": [
+            {"order": 1, "stopId": "36232626", "stopName": "Princes Street", "day": 0,
+             "time": "14:25", "minutes": -2, "reliability": "H", "type": "R",
+             "busStopDisruption": false},
+            {"order": 2, "stopId": "36232627", "stopName": "York Place", "day": 0,
+             "time": "14:30", "minutes": 3, "reliability": "H", "type": "N",
+             "busStopDisruption": true},
+            {"order": 3, "stopId": "36232628", "stopName": "Leith Walk", "day": 0,
+             "time": "14:40", "minutes": 13, "reliability": "H", "type": "R",
+             "busStopDisruption": false}
+        ],
+        "globalDisruption": false, "serviceDisruption": false, "serviceDiversion": false
+    }]}"#;
+
+    const TOPO_ID: &str = r#"{"topoId": "20180101", "operatorId": "LB"}"#;
+
+    const SERVICES: &str = r#"{"services": [
+        {"ref": "26", "operatorId": "LB", "mnemo": "26", "name": "Clerwood - Seton Sands",
+         "dests": ["26A", "26B"]},
+        {"ref": "1", "operatorId": "LB", "mnemo": "1", "name": "Clermiston - Easter Road",
+         "dests": ["1A"]}
+    ]}"#;
+
+    const SERVICE_POINTS: &str = r#"{"ref": "26", "operatorId": "LB", "servicePoints": [
+        {"chainage": 0, "order": 1, "x": 325000.5, "y": 673000.5},
+        {"chainage": 100, "order": 2, "x": 325100.5, "y": 673000.5}
+    ]}"#;
+
+    const DESTINATIONS: &str = r#"{"dests": [
+        {"ref": "26A", "operatorId": "LB", "name": "Seton Sands", "direction": "A",
+         "service": "26"},
+        {"ref": "26B", "operatorId": "LB", "name": "Clerwood", "direction": "R",
+         "service": "26"},
+        {"ref": "1A", "operatorId": "LB", "name": "Easter Road", "direction": "A",
+         "service": "1"}
+    ]}"#;
+
+    const BUS_STOPS: &str = r#"{"busStops": [
+        {"operatorId": "LB", "stopId": "36232626", "name": "Princes Street",
+         "x": 325500.5, "y": 673800.5, "cap": 90, "services": ["26"], "dests": ["26A"]}
+    ]}"#;
+
+    const DISRUPTIONS: &str = r#"{"disruptions": [
+        {"id": "d1", "operatorId": "LB", "level": 3, "type": 2, "targets": ["26"],
+         "validUntil": "2018-01-02T03:04:05Z", "message": "<p>Diverted</p>"},
+        {"id": "d2", "operatorId": "LB", "level": 1, "type": 3, "targets": ["36232626"],
+         "validUntil": null, "message": "Stop closed"}
+    ]}"#;
+
+    const DIVERSIONS: &str = r#"{"diversions": [{
+        "ref": "v1", "diversionId": "123", "operatorId": "LB", "refService": "26",
+        "startStopId": "1", "startStopName": "A", "startDate": "2018-01-01T00:00:00Z",
+        "endStopId": "2", "endStopName": "B", "endDate": "2018-01-31T00:00:00Z",
+        "days": "1111100", "length": 1200, "timeShift": 5,
+        "cancelledBusStops": [
+            {"stopId": "3", "stopName": "C", "replacedStopId": "4", "replacedStopName": "D"}
+        ],
+        "temporaryBusStops": [{"stopId": "5", "stopName": "E", "num": 1, "type": "N"}]
+    }]}"#;
+
+    const DIVERSION_POINTS: &str = r#"{"diversionId": "123", "diversionPoints": [
+        {"order": 1, "x": 325000.5, "y": 673000.5}
+    ]}"#;
+
+    fn bus_times() -> BusTimes {
+        serde_json::from_str(BUS_TIMES).unwrap()
+    }
+
+    fn disruptions() -> Disruptions {
+        serde_json::from_str(DISRUPTIONS).unwrap()
+    }
+
+    fn bus_stop(stop_id: &str, name: &str, easting: f64, northing: f64) -> BusStop {
+        BusStop {
+            operator_id: Operator::LothianBuses,
+            stop_id: stop_id.into(),
+            name: name.to_owned(),
+            easting,
+            northing,
+            orientation: 0,
+            services: vec!["26".to_owned()],
+            destinations: vec!["26A".to_owned()],
+        }
+    }
+
+    /// Princes Street, a stop a kilometre east, and another ten kilometres west.
+    fn bus_stops() -> BusStops {
+        BusStops {
+            bus_stops: vec![
+                bus_stop("1", "Princes Street", 325_600.0, 673_900.0),
+                bus_stop("2", "Leith Street", 326_600.0, 673_900.0),
+                bus_stop("3", "Ratho Station", 315_600.0, 673_900.0),
+            ],
+        }
+    }
+
+    fn stop_ids(bus_stops: &[&BusStop]) -> Vec<String> {
+        bus_stops
+            .iter()
+            .map(|bus_stop| bus_stop.stop_id.to_string())
+            .collect()
+    }
+
+    /// Deserialize, then serialize, the given JSON, checking the result matches the input.
+    fn assert_round_trip<T: DeserializeOwned + Serialize>(json: &str) {
+        let original: serde_json::Value = serde_json::from_str(json).unwrap();
+        let model: T = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_value(&model).unwrap(), original);
+    }
+
+    #[test]
+    fn models_serialize_back_to_the_wire_format() {
+        assert_round_trip::<BusTimes>(BUS_TIMES);
+        assert_round_trip::<JourneyTimes>(JOURNEY_TIMES);
+        assert_round_trip::<TopoId>(TOPO_ID);
+        assert_round_trip::<Services>(SERVICES);
+        assert_round_trip::<ServicePoints>(SERVICE_POINTS);
+        assert_round_trip::<Destinations>(DESTINATIONS);
+        assert_round_trip::<BusStops>(BUS_STOPS);
+        assert_round_trip::<Disruptions>(DISRUPTIONS);
+        assert_round_trip::<Diversions>(DIVERSIONS);
+        assert_round_trip::<DiversionPoints>(DIVERSION_POINTS);
+    }
+
+    #[test]
+    fn unknown_operators_are_kept_as_other() {
+        let operator: Operator = serde_json::from_str(r#""EDT""#).unwrap();
+        assert_eq!(operator, Operator::Other("EDT".to_owned()));
+        assert_eq!(serde_json::to_string(&operator).unwrap(), r#""EDT""#);
+        assert!(serde_json::from_str::<Operator>(r#""""#).is_err());
+    }
+
+    #[test]
+    fn operators_round_trip_through_their_codes() {
+        let operators = [
+            Operator::LothianBuses,
+            Operator::AllOperators,
+            Operator::Other("EDT".to_owned()),
+        ];
+        for operator in &operators {
+            assert_eq!(operator.to_string().parse::<Operator>().unwrap(), *operator);
+        }
+        assert_eq!("ALL".parse::<Operator>().unwrap(), Operator::AllOperators);
+
+        let set: HashSet<Operator> = operators.iter().cloned().chain(operators.clone()).collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn clock_times_are_parsed() {
+        let mut time = bus_times().bus_times[0].times[0].clone();
+        assert_eq!(time.parsed_time(), NaiveTime::from_hms_opt(14, 35, 0));
+
+        time.time = "DUE".to_owned();
+        assert_eq!(time.parsed_time(), None);
+    }
+
+    #[test]
+    fn arrival_is_the_minutes_after_the_reference_time() {
+        let reference = Utc.with_ymd_and_hms(2018, 1, 2, 23, 0, 0).unwrap();
+        let times = &bus_times().bus_times[0].times;
+        assert_eq!(
+            times[0].arrival_at(reference),
+            Utc.with_ymd_and_hms(2018, 1, 2, 23, 3, 0).unwrap()
+        );
+        assert_eq!(
+            times[1].arrival_at(reference),
+            Utc.with_ymd_and_hms(2018, 1, 3, 0, 38, 0).unwrap()
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn day_offsets_are_added_to_the_reference_date() {
+        let reference = Utc.with_ymd_and_hms(2018, 12, 31, 12, 0, 0).unwrap().date();
+        let next_day = Utc.with_ymd_and_hms(2019, 1, 1, 12, 0, 0).unwrap().date();
+
+        let times = &bus_times().bus_times[0].times;
+        assert_eq!(times[0].date(reference), reference);
+        assert_eq!(times[1].date(reference), next_day);
+
+        let journey_times: JourneyTimes = serde_json::from_str(JOURNEY_TIMES).unwrap();
+        let mut stop = journey_times.journey_times[0].journey_times[0].clone();
+        assert_eq!(stop.date(reference), reference);
+        stop.day = 1;
+        assert_eq!(stop.date(reference), next_day);
+    }
+
+    #[test]
+    fn only_realtime_reliabilities_know_about_low_floor_buses() {
+        assert!(Reliability::RealTimeLowFloorEquipped.is_realtime());
+        assert!(Reliability::RealTimeNotLowFloorEquipped.is_realtime());
+        assert!(!Reliability::Estimated.is_realtime());
+        assert!(!Reliability::Delayed.is_realtime());
+
+        assert_eq!(Reliability::RealTimeLowFloorEquipped.is_low_floor(), Some(true));
+        assert_eq!(Reliability::RealTimeNotLowFloorEquipped.is_low_floor(), Some(false));
+        assert_eq!(Reliability::Estimated.is_low_floor(), None);
+        assert!(Reliability::Estimated.is_estimated());
+    }
+
+    #[test]
+    fn directions_round_trip_through_their_codes() {
+        for direction in &[Direction::Inbound, Direction::Outbound] {
+            assert_eq!(direction.to_string().parse::<Direction>(), Ok(*direction));
+        }
+        assert_eq!(Direction::Inbound.to_string(), "A");
+        assert!("X".parse::<Direction>().is_err());
+    }
+
+    #[test]
+    fn disruption_levels_are_ordered_by_severity() {
+        assert!(DisruptionLevel::Major > DisruptionLevel::Minor);
+        assert!(DisruptionLevel::Minor > DisruptionLevel::Informative);
+    }
+
+    #[test]
+    fn disruptions_are_active_until_they_expire() {
+        let disruptions = disruptions();
+        let (expiring, open_ended) = (&disruptions.disruptions[0], &disruptions.disruptions[1]);
+        let before = Utc.with_ymd_and_hms(2018, 1, 2, 3, 0, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2018, 1, 2, 4, 0, 0).unwrap();
+
+        assert!(expiring.is_active(before));
+        assert!(!expiring.is_active(after));
+        assert!(open_ended.is_active(before));
+        assert!(open_ended.is_active(after));
+
+        let active: Vec<&str> = disruptions
+            .active(after)
+            .iter()
+            .map(|disruption| disruption.id.as_str())
+            .collect();
+        assert_eq!(active, vec!["d2"]);
+    }
+
+    #[test]
+    fn days_are_parsed_from_a_weekly_mask() {
+        let diversions: Diversions = serde_json::from_str(DIVERSIONS).unwrap();
+        let weekdays = diversions.diversions[0].days_of_week().unwrap();
+        assert!(weekdays.applies_on(Weekday::Mon));
+        assert!(weekdays.applies_on(Weekday::Fri));
+        assert!(!weekdays.applies_on(Weekday::Sat));
+        assert!(!weekdays.applies_on(Weekday::Sun));
+
+        let weekends: DaysOfWeek = "_____SS".parse().unwrap();
+        assert!(!weekends.applies_on(Weekday::Mon));
+        assert!(weekends.applies_on(Weekday::Sun));
+        assert_eq!("MTWTF-?".parse::<DaysOfWeek>(), "1111101".parse());
+        assert!("11111".parse::<DaysOfWeek>().is_err());
+    }
+
+    #[test]
+    fn stop_ids_are_plain_strings_on_the_wire() {
+        let stop_id: StopId = serde_json::from_str(r#""36232626""#).unwrap();
+        assert_eq!(stop_id, StopId::from("36232626"));
+        assert_eq!(stop_id.as_str(), "36232626");
+        assert_eq!(stop_id.to_string(), "36232626");
+        assert_eq!(serde_json::to_string(&stop_id).unwrap(), r#""36232626""#);
+    }
+
+    #[test]
+    fn timetables_with_empty_fields_are_rejected() {
+        let invalid_field = |stop_id: &str, service: &str, destination: &str| {
+            match Timetable::new(stop_id.into(), service, destination, Operator::LothianBuses) {
+                Err(MyBusTrackerError::InvalidTimetable { field }) => Some(field),
+                _ => None,
+            }
+        };
+        assert_eq!(invalid_field("", "26", "26A"), Some("stop_id"));
+        assert_eq!(invalid_field("36232626", " ", "26A"), Some("service_reference"));
+        assert_eq!(invalid_field("36232626", "26", ""), Some("destination_reference"));
+        assert_eq!(invalid_field("36232626", "26", "26A"), None);
+        assert!(Timetable::new("36232626".into(), "26", "26A", Operator::LothianBuses).is_ok());
+    }
+
+    #[test]
+    fn bus_times_are_sorted_and_filtered_by_service() {
+        let mut bus_times = bus_times();
+        let mut service_22 = bus_times.bus_times[0].clone();
+        service_22.service_mnemonic = "22".to_owned();
+        service_22.times.truncate(1);
+        service_22.times[0].minutes = 1;
+        let mut no_departures = service_22.clone();
+        no_departures.service_mnemonic = "X".to_owned();
+        no_departures.times.clear();
+        bus_times.bus_times.push(no_departures);
+        bus_times.bus_times.push(service_22);
+
+        let sorted: Vec<&str> = bus_times
+            .sorted_by_arrival()
+            .iter()
+            .map(|bus_time| bus_time.service_mnemonic.as_str())
+            .collect();
+        assert_eq!(sorted, vec!["22", "26", "X"]);
+
+        assert_eq!(bus_times.for_service("26").len(), 1);
+        assert!(bus_times.for_service("99").is_empty());
+
+        let journeys: Vec<&str> = bus_times
+            .all_times()
+            .map(|(_, time)| time.journey_id.as_str())
+            .collect();
+        assert_eq!(journeys, vec!["1234", "1235", "1234"]);
+    }
+
+    #[test]
+    fn stops_within_a_bounding_box_include_its_boundary() {
+        let bus_stops = bus_stops();
+        let (lat, lon) = bus_stops.bus_stops[0].wgs84();
+        let (_, east_lon) = bus_stops.bus_stops[1].wgs84();
+
+        let within = bus_stops.within_bbox(lat - 0.01, lon, lat + 0.01, east_lon);
+        assert_eq!(stop_ids(&within), vec!["1", "2"]);
+        assert!(bus_stops.within_bbox(0.0, 0.0, 1.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn nearest_stops_are_ordered_by_distance() {
+        let bus_stops = bus_stops();
+        let (lat, lon) = bus_stops.bus_stops[1].wgs84();
+
+        let nearest = bus_stops.nearest(lat, lon, 2);
+        let ids: Vec<&str> = nearest.iter().map(|(stop, _)| stop.stop_id.as_str()).collect();
+        assert_eq!(ids, vec!["2", "1"]);
+        assert_eq!(nearest[0].1, 0.0);
+        assert!((nearest[1].1 - 1000.0).abs() < 10.0, "{}", nearest[1].1);
+
+        assert_eq!(bus_stops.nearest(lat, lon, 10).len(), 3);
+    }
+
+    #[test]
+    fn stops_are_searched_by_name_ignoring_case() {
+        let bus_stops = bus_stops();
+        assert_eq!(stop_ids(&bus_stops.search(" STREET ")), vec!["1", "2"]);
+        assert_eq!(stop_ids(&bus_stops.search("ratho")), vec!["3"]);
+        assert!(bus_stops.search("  ").is_empty());
+    }
+
+    #[test]
+    fn service_references_are_resolved() {
+        let services: Services = serde_json::from_str(SERVICES).unwrap();
+        assert_eq!(services.by_reference("1").unwrap().mnemonic, "1");
+        assert_eq!(services.by_reference("99"), None);
+
+        let references = vec!["26".to_owned(), "99".to_owned(), "1".to_owned()];
+        let resolved: Vec<&str> = services
+            .resolve(&references)
+            .iter()
+            .map(|service| service.reference.as_str())
+            .collect();
+        assert_eq!(resolved, vec!["26", "1"]);
+    }
+
+    #[test]
+    fn polylines_are_in_ascending_order() {
+        let service_points: ServicePoints = serde_json::from_str(
+            r#"{"ref": "26", "operatorId": "LB", "servicePoints": [
+                {"chainage": 200, "order": 3, "x": 325200.0, "y": 673000.0},
+                {"chainage": 0, "order": 1, "x": 325000.0, "y": 673000.0},
+                {"chainage": 100, "order": 2, "x": 325100.0, "y": 673000.0},
+                {"chainage": 100, "order": 2, "x": 999999.0, "y": 999999.0}
+            ]}"#,
+        )
+        .unwrap();
+
+        let polyline = service_points.to_polyline();
+        let expected: Vec<(f64, f64)> = [325_000.0, 325_100.0, 325_200.0]
+            .iter()
+            .map(|&easting| geo::osgb36_to_wgs84(easting, 673_000.0))
+            .collect();
+        assert_eq!(polyline, expected);
+        assert!(polyline.windows(2).all(|pair| pair[0].1 < pair[1].1));
+
+        let geojson = service_points.to_geojson();
+        assert_eq!(geojson["geometry"]["type"], "LineString");
+        assert_eq!(geojson["geometry"]["coordinates"].as_array().unwrap().len(), 3);
+        assert_eq!(geojson["properties"]["service_reference"], "26");
+    }
+
+    #[test]
+    fn bus_stop_geojson_has_a_lon_lat_point_per_stop() {
+        let bus_stops = bus_stops();
+        let geojson = bus_stops.to_geojson();
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 3);
+
+        let (lat, lon) = bus_stops.bus_stops[0].wgs84();
+        let feature = &features[0];
+        assert_eq!(feature["geometry"]["type"], "Point");
+        assert_eq!(feature["geometry"]["coordinates"], serde_json::json!([lon, lat]));
+        assert_eq!(feature["properties"]["stop_id"], "1");
+        assert_eq!(feature["properties"]["name"], "Princes Street");
+        assert_eq!(feature["properties"]["services"], serde_json::json!(["26"]));
+    }
+
+    #[test]
+    fn diversion_geojson_is_a_sorted_line_string() {
+        let diversion_points: DiversionPoints = serde_json::from_str(
+            r#"{"diversionId": "123", "diversionPoints": [
+                {"order": 2, "x": 325100.0, "y": 673000.0},
+                {"order": 1, "x": 325000.0, "y": 673000.0}
+            ]}"#,
+        )
+        .unwrap();
+
+        let geojson = diversion_points.to_geojson();
+        assert_eq!(geojson["type"], "Feature");
+        assert_eq!(geojson["geometry"]["type"], "LineString");
+        assert_eq!(geojson["properties"]["diversion_id"], "123");
+
+        let (lat, lon) = diversion_points.diversion_points[1].wgs84();
+        let coordinates = geojson["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(coordinates.len(), 2);
+        assert_eq!(coordinates[0], serde_json::json!([lon, lat]));
+    }
+
+    #[test]
+    fn changes_between_two_snapshots() {
+        let previous = disruptions();
+        let mut current = disruptions();
+        current.disruptions[0].message = "<p>Diverted via Leith Walk</p>".to_owned();
+        current.disruptions.remove(1);
+        let mut added = previous.disruptions[1].clone();
+        added.id = "d3".to_owned();
+        current.disruptions.push(added.clone());
+
+        assert_eq!(
+            current.changes_since(&previous),
+            vec![
+                DisruptionEvent::Changed(current.disruptions[0].clone()),
+                DisruptionEvent::Added(added),
+                DisruptionEvent::Removed("d2".to_owned()),
+            ]
+        );
+        assert!(previous.changes_since(&previous).is_empty());
+    }
+
+    #[test]
+    fn route_length_sums_the_sorted_points() {
+        let mut service_points: ServicePoints = serde_json::from_str(
+            r#"{"ref": "26", "operatorId": "LB", "servicePoints": [
+                {"chainage": 0, "order": 1, "x": 325000.0, "y": 673000.0},
+                {"chainage": 200, "order": 3, "x": 325200.0, "y": 673000.0},
+                {"chainage": 100, "order": 2, "x": 325100.0, "y": 673000.0}
+            ]}"#,
+        )
+        .unwrap();
+
+        // Listed in order, the route would double back on itself, and be 300 metres long.
+        let length = service_points.route_length_metres();
+        assert!((length - 200.0).abs() < 1.0, "{}", length);
+
+        service_points.service_points.truncate(1);
+        assert_eq!(service_points.route_length_metres(), 0.0);
+    }
+
+    #[test]
+    fn stop_types_are_decoded() {
+        let stop_type = |code: &str| -> StopType {
+            serde_json::from_value(serde_json::Value::String(code.to_owned())).unwrap()
+        };
+        assert_eq!(stop_type("N"), StopType::Normal);
+        assert_eq!(stop_type("R"), StopType::Reference);
+        assert_eq!(stop_type("X"), StopType::Other("X".to_owned()));
+        assert_eq!(
+            serde_json::to_value(stop_type("X")).unwrap(),
+            serde_json::Value::String("X".to_owned())
+        );
+
+        let diversions: Diversions = serde_json::from_str(DIVERSIONS).unwrap();
+        let temporary = &diversions.diversions[0].temporary_bus_stops[0];
+        assert_eq!(temporary.stop_type, StopType::Normal);
+    }
+
+    #[test]
+    fn all_operators_lists_only_concrete_operators() {
+        assert_eq!(Operator::all(), &[Operator::LothianBuses]);
+        assert!(!Operator::all().contains(&Operator::AllOperators));
+        assert!(Operator::all()
+            .iter()
+            .all(|operator| !matches!(operator, Operator::Other(_))));
+    }
+
+    #[test]
+    fn stops_are_partitioned_by_operator() {
+        let mut bus_stops = bus_stops();
+        bus_stops.bus_stops[1].operator_id = Operator::Other("EDT".to_owned());
+
+        let lothian = bus_stops.by_operator(&Operator::LothianBuses);
+        let edinburgh_trams = bus_stops.by_operator(&Operator::Other("EDT".to_owned()));
+        assert_eq!(stop_ids(&lothian), vec!["1", "3"]);
+        assert_eq!(stop_ids(&edinburgh_trams), vec!["2"]);
+        assert_eq!(bus_stops.by_operator(&Operator::AllOperators).len(), 3);
+    }
+
+    #[test]
+    fn decoded_models_compare_equal_to_expected_values() {
+        let bus_stops: BusStops = serde_json::from_str(
+            r#"{"busStops": [{"operatorId": "LB", "stopId": "1", "name": "Princes Street",
+                "x": 325600.0, "y": 673900.0, "cap": 0, "services": ["26"],
+                "dests": ["26A"]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            bus_stops.bus_stops,
+            vec![bus_stop("1", "Princes Street", 325_600.0, 673_900.0)]
+        );
+
+        let services: Services = serde_json::from_str(SERVICES).unwrap();
+        assert_eq!(
+            services.services[1],
+            Service {
+                reference: "1".to_owned(),
+                operator_id: Operator::LothianBuses,
+                mnemonic: "1".to_owned(),
+                name: "Clermiston - Easter Road".to_owned(),
+                destinations: vec!["1A".to_owned()],
+            }
+        );
+        assert_ne!(services.services[0], services.services[1]);
+        assert_eq!(disruptions(), disruptions());
+    }
+
+    #[test]
+    fn times_of_day_keep_their_seconds() {
+        for &time in &["14:35", "14:35:07", "00:00"] {
+            let json = serde_json::Value::String(time.to_owned());
+            let parsed: NaiveTimeExt = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+        }
+        assert_eq!(
+            serde_json::to_value(NaiveTimeExt::from(NaiveTime::from_hms_opt(9, 5, 0).unwrap()))
+                .unwrap(),
+            "09:05"
+        );
+        assert!(serde_json::from_str::<NaiveTimeExt>(r#""DUE""#).is_err());
+    }
+
+    #[test]
+    fn clock_times_are_parsed_in_every_format() {
+        let mut time = bus_times().bus_times[0].times[0].clone();
+        for format in &NAIVE_TIME_FORMATS {
+            let expected = NaiveTime::from_hms_opt(14, 35, 0).unwrap();
+            time.time = expected.format(format).to_string();
+            assert_eq!(time.parsed_time(), Some(expected), "{}", format);
+        }
+        time.time = "14:35:07".to_owned();
+        assert_eq!(time.parsed_time(), NaiveTime::from_hms_opt(14, 35, 7));
+    }
+
+    #[test]
+    fn fieldless_enums_are_copy() {
+        fn assert_copy<T: Copy>() {}
+        assert_copy::<Reliability>();
+        assert_copy::<Direction>();
+        assert_copy::<DisruptionType>();
+        assert_copy::<DisruptionLevel>();
+        assert_copy::<JourneyTimeMode>();
+
+        let level = DisruptionLevel::Major;
+        let copied = level;
+        assert_eq!(level, copied);
+    }
+
+    #[test]
+    fn bus_times_are_summarised_by_their_soonest_departure() {
+        let mut bus_time = bus_times().bus_times[0].clone();
+        assert_eq!(bus_time.to_string(), "Service 26 to Seton Sands — 3 min (real-time)");
+
+        bus_time.destination_name = None;
+        bus_time.times.swap(0, 1);
+        bus_time.times[0].terminus = "Tower Place".to_owned();
+        bus_time.times[1].terminus = "Port Seton".to_owned();
+        assert_eq!(bus_time.to_string(), "Service 26 to Port Seton — 3 min (real-time)");
+
+        bus_time.times.truncate(1);
+        assert_eq!(bus_time.to_string(), "Service 26 to Tower Place — 98 min (timetabled)");
+
+        bus_time.times.clear();
+        assert_eq!(bus_time.to_string(), "Service 26 — no departures");
+    }
+
+    #[test]
+    fn journey_stops_passed_have_departed() {
+        let journey_times: JourneyTimes = serde_json::from_str(JOURNEY_TIMES).unwrap();
+        let stops = &journey_times.journey_times[0].journey_times;
+
+        assert!(stops[0].has_departed());
+        assert_eq!(stops[0].minutes_until(), None);
+        assert!(!stops[1].has_departed());
+        assert_eq!(stops[1].minutes_until(), Some(3));
+
+        let mut due = stops[1].clone();
+        due.minutes = 0;
+        assert!(!due.has_departed());
+        assert_eq!(due.minutes_until(), Some(0));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn departure_days_are_offsets_from_the_request() {
+        let reference = Utc.with_ymd_and_hms(2018, 12, 31, 23, 0, 0).unwrap().date();
+        let mut time = bus_times().bus_times[0].times[0].clone();
+
+        let days: Vec<(bool, bool, Date<Utc>)> = (0..3)
+            .map(|day| {
+                time.day = day;
+                (time.is_today(), time.is_tomorrow(), time.service_date(reference))
+            })
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                (true, false, Utc.with_ymd_and_hms(2018, 12, 31, 0, 0, 0).unwrap().date()),
+                (false, true, Utc.with_ymd_and_hms(2019, 1, 1, 0, 0, 0).unwrap().date()),
+                (false, false, Utc.with_ymd_and_hms(2019, 1, 2, 0, 0, 0).unwrap().date()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bearings_are_bucketed_into_compass_points() {
+        let cases = [
+            (0, CompassPoint::North),
+            (22, CompassPoint::North),
+            (23, CompassPoint::NorthEast),
+            (90, CompassPoint::East),
+            (157, CompassPoint::SouthEast),
+            (158, CompassPoint::South),
+            (225, CompassPoint::SouthWest),
+            (270, CompassPoint::West),
+            (337, CompassPoint::NorthWest),
+            (338, CompassPoint::North),
+            (360, CompassPoint::North),
+            (405, CompassPoint::NorthEast),
+            (u16::MAX, CompassPoint::from_bearing(u16::MAX % 360)),
+        ];
+        for &(bearing, expected) in &cases {
+            assert_eq!(CompassPoint::from_bearing(bearing), expected, "{}", bearing);
+        }
+
+        let mut stop = bus_stop("1", "Princes Street", 325_600.0, 673_900.0);
+        stop.orientation = 180;
+        assert_eq!(stop.compass_direction(), CompassPoint::South);
+        assert_eq!(stop.compass_direction().to_string(), "south");
+    }
+
+    #[test]
+    fn only_realtime_departures_are_kept() {
+        let bus_times = bus_times();
+        let realtime = bus_times.realtime_only();
+
+        assert_eq!(realtime.len(), 1);
+        let (bus_time, time) = realtime[0];
+        assert_eq!(bus_time.service_mnemonic, "26");
+        assert_eq!(time.journey_id, "1234");
+        assert_eq!(time.reliability, Reliability::RealTimeLowFloorEquipped);
+    }
+
+    #[test]
+    fn stop_only_timetables_leave_the_references_blank() {
+        let timetable = Timetable::for_stop("36232626".into(), Operator::LothianBuses).unwrap();
+        assert_eq!(timetable.stop_id.as_str(), "36232626");
+        assert_eq!(timetable.service_reference, "");
+        assert_eq!(timetable.destination_reference, "");
+
+        assert!(matches!(
+            Timetable::for_stop(" ".into(), Operator::LothianBuses),
+            Err(MyBusTrackerError::InvalidTimetable { field: "stop_id" })
+        ));
+    }
+
+    #[test]
+    fn there_is_no_next_reference_once_every_reference_stop_has_departed() {
+        let mut journey_times: JourneyTimes = serde_json::from_str(JOURNEY_TIMES).unwrap();
+        assert_eq!(journey_times.next_reference().unwrap().order, 3);
+
+        journey_times.journey_times[0].journey_times.truncate(2);
+        assert_eq!(journey_times.next_reference(), None);
+    }
+
+    #[test]
+    fn omitted_disruption_flags_default_to_false() {
+        let flags = [
+            "globalDisruption",
+            "serviceDisruption",
+            "busStopDisruption",
+            "serviceDiversion",
+        ];
+        let without_flags = |fixture: &str, list: &str| {
+            let mut value: serde_json::Value = serde_json::from_str(fixture).unwrap();
+            let item = value[list][0].as_object_mut().unwrap();
+            for flag in &flags {
+                item.remove(*flag);
+            }
+            value
+        };
+
+        let bus_times: BusTimes =
+            serde_json::from_value(without_flags(BUS_TIMES, "busTimes")).unwrap();
+        let bus_time = &bus_times.bus_times[0];
+        assert!(!bus_time.global_disruption);
+        assert!(!bus_time.service_disruption);
+        assert!(!bus_time.bus_stop_disruption);
+        assert!(!bus_time.service_diversion);
+
+        let journey_times: JourneyTimes =
+            serde_json::from_value(without_flags(JOURNEY_TIMES, "journeyTimes")).unwrap();
+        let journey_time = &journey_times.journey_times[0];
+        assert!(!journey_time.global_disruption);
+        assert!(!journey_time.service_disruption);
+        assert!(!journey_time.service_diversion);
+    }
+
+    #[test]
+    fn destinations_are_filtered_by_service() {
+        let destinations: Destinations = serde_json::from_str(DESTINATIONS).unwrap();
+        let references = |service| {
+            destinations
+                .for_service(service)
+                .iter()
+                .map(|destination| destination.reference.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(references("26"), ["26A", "26B"]);
+        assert_eq!(references("1"), ["1A"]);
+        assert!(references("99").is_empty());
+    }
+
+    #[test]
+    fn destinations_are_partitioned_by_direction() {
+        let destinations: Destinations = serde_json::from_str(DESTINATIONS).unwrap();
+        let (inbound, outbound) = destinations.by_direction();
+
+        let references = |bucket: &[&Destination]| {
+            bucket
+                .iter()
+                .map(|destination| destination.reference.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(references(&inbound), ["26A", "1A"]);
+        assert_eq!(references(&outbound), ["26B"]);
+        assert!(inbound.iter().all(|destination| destination.direction == Direction::Inbound));
     }
 }
@@ -0,0 +1,155 @@
+//! Stop Overview
+//!
+//! Joins the responses of several web services into everything needed to describe one stop.
+
+use futures_util::future::try_join4;
+use hyper_util::client::legacy::connect::Connect;
+
+use super::{
+    models, BusTimesService, DisruptionsServices, MyBusTracker, MyBusTrackerError,
+    TopologicalServices,
+};
+
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Get an overview of the given stop: its details, the services calling at it, the next
+    /// `departure_count` departures of each, and any disruptions affecting it.
+    ///
+    /// The underlying requests are made concurrently. Fails with
+    /// `MyBusTrackerError::UnknownStop` if the operator has no such stop.
+    pub async fn get_stop_overview(
+        &self,
+        stop_id: &models::StopId,
+        departure_count: u8,
+        operator: &models::Operator,
+    ) -> Result<models::StopOverview, MyBusTrackerError> {
+        debug!(
+            self.logger,
+            "Getting stop overview";
+            "stop_id" => %stop_id,
+            "departures" => departure_count,
+            "operator" => ?operator,
+        );
+        let (bus_stops, services, times, disruptions) = try_join4(
+            self.get_bus_stops(operator),
+            self.get_services(operator),
+            self.get_bus_times_for_stop(stop_id, departure_count),
+            self.get_disruptions_for_stop(stop_id, operator),
+        ).await?;
+
+        let stop = bus_stops
+            .bus_stops
+            .into_iter()
+            .find(|bus_stop| bus_stop.stop_id == *stop_id)
+            .ok_or_else(|| MyBusTrackerError::UnknownStop {
+                stop_id: stop_id.clone(),
+            })?;
+        let services = services
+            .resolve(&stop.services)
+            .into_iter()
+            .cloned()
+            .collect();
+
+        Ok(models::StopOverview {
+            stop,
+            services,
+            times,
+            disruptions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::serve_with;
+
+    const BUS_STOPS: &str = r#"{"busStops": [
+        {"operatorId": "LB", "stopId": "36232626", "name": "Princes Street",
+         "x": 325500.5, "y": 673800.5, "cap": 90, "services": ["26", "99"], "dests": ["26A"]}
+    ]}"#;
+
+    const SERVICES: &str = r#"{"services": [
+        {"ref": "26", "operatorId": "LB", "mnemo": "26", "name": "Clerwood - Seton Sands",
+         "dests": ["26A", "26B"]},
+        {"ref": "1", "operatorId": "LB", "mnemo": "1", "name": "Clermiston - Easter Road",
+         "dests": ["1A"]}
+    ]}"#;
+
+    const DISRUPTIONS: &str = r#"{"disruptions": [
+        {"id": "d1", "operatorId": "LB", "level": 1, "type": 3, "targets": ["36232626"],
+         "validUntil": null, "message": "Stop closed"},
+        {"id": "d2", "operatorId": "LB", "level": 1, "type": 3, "targets": ["36232627"],
+         "validUntil": null, "message": "Stop closed"}
+    ]}"#;
+
+    /// A tracker whose server answers each function with the matching response above.
+    async fn tracker() -> MyBusTracker {
+        let base_url = serve_with(|request| {
+            let function = request
+                .split(['?', '&', ' '])
+                .find_map(|pair| pair.strip_prefix("function="))
+                .unwrap();
+            let body = match function {
+                "getBusStops" => BUS_STOPS,
+                "getServices" => SERVICES,
+                "getBusTimes" => r#"{"busTimes": []}"#,
+                "getDisruptions" => DISRUPTIONS,
+                function => panic!("unexpected function: {}", function),
+            };
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .into_bytes()
+        })
+        .await;
+        MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(base_url)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn overviews_join_the_stop_services_and_disruptions() {
+        let overview = tracker()
+            .await
+            .get_stop_overview(&"36232626".into(), 2, &models::Operator::LothianBuses)
+            .await
+            .unwrap();
+
+        assert_eq!(overview.stop.name, "Princes Street");
+        let services: Vec<&str> = overview
+            .services
+            .iter()
+            .map(|service| service.reference.as_str())
+            .collect();
+        assert_eq!(services, vec!["26"]);
+        assert!(overview.times.bus_times.is_empty());
+        let disruptions: Vec<&str> = overview
+            .disruptions
+            .disruptions
+            .iter()
+            .map(|disruption| disruption.id.as_str())
+            .collect();
+        assert_eq!(disruptions, vec!["d1"]);
+    }
+
+    #[tokio::test]
+    async fn overviews_of_unknown_stops_fail() {
+        let error = tracker()
+            .await
+            .get_stop_overview(&"1".into(), 2, &models::Operator::LothianBuses)
+            .await
+            .unwrap_err();
+
+        match error {
+            MyBusTrackerError::UnknownStop { stop_id } => assert_eq!(stop_id.as_str(), "1"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! API Key Refresher
+//!
+//! API keys are only valid for the clock-hour in which they were generated, and are otherwise
+//! regenerated on the first request of each hour. The refresher instead regenerates the key as
+//! each hour begins, in the background, so that no request has to.
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, Timelike};
+use hyper_util::client::legacy::connect::Connect;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+use super::{ApiKey, MyBusTracker};
+
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Spawn a task on the given runtime which regenerates the API key as each hour begins.
+    ///
+    /// The task runs until it is aborted (e.g. with `JoinHandle::abort`), or its runtime shuts
+    /// down. Requests made meanwhile still check, and if necessary regenerate, the key
+    /// themselves, so a late wake-up is harmless.
+    pub fn spawn_key_refresher(&self, handle: &Handle) -> JoinHandle<()> {
+        debug!(self.logger, "Spawning API key refresher");
+        handle.spawn(refresh(Arc::clone(&self.api_key)))
+    }
+}
+
+/// Regenerate the API key at the start of each hour, by its own clock, until its lock is
+/// poisoned.
+async fn refresh(api_key: Arc<Mutex<ApiKey>>) {
+    loop {
+        let now = match api_key.lock() {
+            Ok(api_key) => api_key.clock.now(),
+            Err(_) => return,
+        };
+        let hour_start = now
+            .with_minute(0)
+            .and_then(|time| time.with_second(0))
+            .and_then(|time| time.with_nanosecond(0))
+            .unwrap_or(now);
+        let until_next_hour = (hour_start + Duration::hours(1) - now)
+            .to_std()
+            .unwrap_or_default();
+
+        tokio::time::sleep(until_next_hour).await;
+
+        match api_key.lock() {
+            Ok(mut api_key) => {
+                api_key.get_key();
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::tests::TestClock;
+
+    #[tokio::test(start_paused = true)]
+    async fn keys_are_regenerated_as_each_hour_begins() {
+        let clock = TestClock::at(Utc.with_ymd_and_hms(2018, 1, 2, 3, 59, 0).unwrap());
+        let tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let key = |tracker: &MyBusTracker| tracker.api_key.lock().unwrap().key.clone();
+        let original = key(&tracker);
+
+        let refresher = tracker.spawn_key_refresher(&Handle::current());
+        tokio::task::yield_now().await;
+        clock.set(Utc.with_ymd_and_hms(2018, 1, 2, 4, 0, 0).unwrap());
+        assert_eq!(key(&tracker), original);
+
+        tokio::time::sleep(std::time::Duration::from_secs(61)).await;
+        assert_ne!(key(&tracker), original);
+        assert!(tracker.api_key.lock().unwrap().is_current());
+
+        refresher.abort();
+    }
+}
@@ -0,0 +1,106 @@
+//! Retrying of failed requests
+
+use std::time::Duration;
+
+use super::MyBusTrackerError;
+
+/// Policy for retrying requests which fail with a transient error.
+///
+/// The delay before each retry doubles, starting from `base_delay`.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of retries made after the initial attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a new retry policy.
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            base_delay,
+        }
+    }
+
+    /// A policy under which requests are never retried.
+    pub fn none() -> Self {
+        Self::new(0, Duration::from_secs(0))
+    }
+
+    /// Whether a request which failed with `error` should be retried, given the number of
+    /// retries already made.
+    pub fn should_retry(&self, retries: u32, error: &MyBusTrackerError) -> bool {
+        retries < self.max_retries && error.is_retryable()
+    }
+
+    /// The delay before retrying, given the number of retries already made.
+    pub fn delay(&self, retries: u32) -> Duration {
+        let multiplier = 1u32.checked_shl(retries).unwrap_or(u32::MAX);
+        self.base_delay
+            .checked_mul(multiplier)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn communication_error(status: Option<u16>) -> MyBusTrackerError {
+        MyBusTrackerError::CommunicationError {
+            cause: String::new(),
+            status,
+            uri: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_retried_up_to_the_limit() {
+        let policy = RetryPolicy::new(2, Duration::from_millis(10));
+        for error in &[communication_error(None), communication_error(Some(503))] {
+            assert!(policy.should_retry(0, error));
+            assert!(policy.should_retry(1, error));
+            assert!(!policy.should_retry(2, error));
+        }
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let policy = RetryPolicy::new(2, Duration::from_millis(10));
+        assert!(!policy.should_retry(0, &communication_error(Some(404))));
+        let out_of_bounds = MyBusTrackerError::DateOutOfBounds {
+            days_offset: 4,
+            max: 3,
+        };
+        assert!(!policy.should_retry(0, &out_of_bounds));
+        let too_many = MyBusTrackerError::TooManyTimetables {
+            requested: 6,
+            max: 5,
+        };
+        assert!(!policy.should_retry(0, &too_many));
+    }
+
+    #[test]
+    fn delay_doubles_with_each_retry() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(10));
+        assert_eq!(policy.delay(0), Duration::from_millis(10));
+        assert_eq!(policy.delay(1), Duration::from_millis(20));
+        assert_eq!(policy.delay(2), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn delay_saturates_instead_of_overflowing() {
+        let policy = RetryPolicy::new(3, Duration::MAX / 2);
+        assert_eq!(policy.delay(2), Duration::MAX);
+        assert_eq!(policy.delay(64), Duration::MAX);
+    }
+}
@@ -2,15 +2,17 @@
 //!
 //! For full documentation, see Section IV.2 of the My Bus Tracker API Guide (Version F)
 
-use hyper::{Method, Request};
 use super::{models, MyBusTracker, MyBusTrackerError};
-use futures::{self, Future};
+use futures_util::future::try_join3;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use hyper_util::client::legacy::connect::Connect;
+use std::future::Future;
 
 /// Topological Web Service
 ///
 /// To use methods from the Bus Times Web Service, bring this trait into scope
 /// alongside your `MyBusTracker` instance.
-#[allow(stutter)]
+#[allow(clippy::module_name_repetitions)]
 pub trait TopologicalServices {
     /// Get the ID of the topology version in use.
     ///
@@ -20,80 +22,126 @@ pub trait TopologicalServices {
     fn get_topo_id(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::TopoId, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::TopoId, MyBusTrackerError>> + Send;
 
     /// Get a list of services in operation.
     fn get_services(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Services, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::Services, MyBusTrackerError>> + Send;
 
     /// Get a description of a service route for plotting on a map
     fn get_service_points(
         &self,
         service_reference: &str,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::ServicePoints, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::ServicePoints, MyBusTrackerError>> + Send;
+
+    /// Get a description of the route of a service, as listed by `get_services`.
+    ///
+    /// Unlike `get_service_points`, the service reference and operator are guaranteed to be a
+    /// valid combination, so cannot be mistyped.
+    fn get_service_points_for(
+        &self,
+        service: &models::Service,
+    ) -> impl Future<Output = Result<models::ServicePoints, MyBusTrackerError>> + Send {
+        self.get_service_points(&service.reference, &service.operator_id)
+    }
+
+    /// Get the descriptions of several service routes, making up to `concurrency` requests at
+    /// once.
+    ///
+    /// The routes are returned in the same order as `service_references`. If any request
+    /// fails, the first error is returned.
+    fn get_service_points_batch(
+        &self,
+        service_references: &[&str],
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> impl Future<Output = Result<Vec<models::ServicePoints>, MyBusTrackerError>> + Send;
 
     /// Get a list of service destinations
     fn get_destinations(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Destinations, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::Destinations, MyBusTrackerError>> + Send;
 
     /// Get a list of bus stops
     fn get_bus_stops(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::BusStops, Error = MyBusTrackerError>>;
+    ) -> impl Future<Output = Result<models::BusStops, MyBusTrackerError>> + Send;
+
+    /// Get the services, destinations and bus stops together, with the topology ID they were
+    /// requested under.
+    ///
+    /// The topology ID is requested first, then the rest concurrently. If any request fails,
+    /// the first error is returned.
+    fn get_topology(
+        &self,
+        operator: &models::Operator,
+    ) -> impl Future<Output = Result<models::Topology, MyBusTrackerError>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let id = self.get_topo_id(operator).await?;
+            let (services, destinations, bus_stops) = try_join3(
+                self.get_services(operator),
+                self.get_destinations(operator),
+                self.get_bus_stops(operator),
+            )
+            .await?;
+
+            Ok(models::Topology {
+                id,
+                services,
+                destinations,
+                bus_stops,
+            })
+        }
+    }
 }
 
-impl TopologicalServices for MyBusTracker {
-    fn get_topo_id(
+impl<C> TopologicalServices for MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    async fn get_topo_id(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::TopoId, Error = MyBusTrackerError>> {
+    ) -> Result<models::TopoId, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting topography ID;";
             "operator" => ?operator,
         );
-        let uri_params = format!("operatorId={}", operator.to_string());
-        let uri = match self.get_uri("getTopoId", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri_params = format!("operatorId={}", operator);
+        let uri = self.get_uri("getTopoId", Some(&uri_params))?;
 
-        let request = Request::new(Method::Get, uri);
-
-        self.make_request(request)
+        self.make_request(uri).await
     }
 
-    fn get_services(
+    async fn get_services(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Services, Error = MyBusTrackerError>> {
+    ) -> Result<models::Services, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting services";
             "operator" => ?operator
         );
-        let uri_params = format!("operatorId={}", operator.to_string());
-        let uri = match self.get_uri("getServices", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri_params = format!("operatorId={}", operator);
+        let uri = self.get_uri("getServices", Some(&uri_params))?;
 
-        let request = Request::new(Method::Get, uri);
-
-        self.make_request(request)
+        self.make_request(uri).await
     }
 
-    fn get_service_points(
+    async fn get_service_points(
         &self,
         service_reference: &str,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::ServicePoints, Error = MyBusTrackerError>> {
+    ) -> Result<models::ServicePoints, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting service points";
@@ -102,56 +150,233 @@ impl TopologicalServices for MyBusTracker {
         );
         let uri_params = format!(
             "operatorId={}&ref={}",
-            operator.to_string(),
+            operator,
             service_reference
         );
-        let uri = match self.get_uri("getServicePoints", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri = self.get_uri("getServicePoints", Some(&uri_params))?;
 
-        let request = Request::new(Method::Get, uri);
+        self.make_request(uri).await
+    }
 
-        self.make_request(request)
+    async fn get_service_points_batch(
+        &self,
+        service_references: &[&str],
+        concurrency: usize,
+        operator: &models::Operator,
+    ) -> Result<Vec<models::ServicePoints>, MyBusTrackerError> {
+        debug!(
+            self.logger,
+            "Getting service points for several services";
+            "service_references" => ?service_references,
+            "concurrency" => concurrency,
+            "operator" => ?operator,
+        );
+        // Futures are lazy, so requests are only made as they are buffered.
+        let requests: Vec<_> = service_references
+            .iter()
+            .map(|service_reference| self.get_service_points(service_reference, operator))
+            .collect();
+        stream::iter(requests)
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await
     }
 
-    fn get_destinations(
+    async fn get_destinations(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::Destinations, Error = MyBusTrackerError>> {
+    ) -> Result<models::Destinations, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting destinations";
             "operator" => ?operator
         );
-        let uri_params = format!("operatorId={}", operator.to_string());
-        let uri = match self.get_uri("getDests", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
-
-        let request = Request::new(Method::Get, uri);
+        let uri_params = format!("operatorId={}", operator);
+        let uri = self.get_uri("getDests", Some(&uri_params))?;
 
-        self.make_request(request)
+        self.make_request(uri).await
     }
 
-    fn get_bus_stops(
+    async fn get_bus_stops(
         &self,
         operator: &models::Operator,
-    ) -> Box<Future<Item = models::BusStops, Error = MyBusTrackerError>> {
+    ) -> Result<models::BusStops, MyBusTrackerError> {
         debug!(
             self.logger,
             "Getting bus stops";
             "operator" => ?operator,
         );
-        let uri_params = format!("operatorId={}", operator.to_string());
-        let uri = match self.get_uri("getBusStops", Some(&uri_params)) {
-            Ok(uri) => uri,
-            Err(uri_error) => return Box::new(futures::failed(uri_error)),
-        };
+        let uri_params = format!("operatorId={}", operator);
+        let uri = self.get_uri("getBusStops", Some(&uri_params))?;
+
+        self.make_request(uri).await
+    }
+}
+
+/// Variants of the Topological Web Service methods, using the instance's default operator.
+///
+/// See `MyBusTracker::set_default_operator`.
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// See `TopologicalServices::get_topo_id`.
+    pub async fn get_topo_id_default(&self) -> Result<models::TopoId, MyBusTrackerError> {
+        self.get_topo_id(&self.default_operator).await
+    }
+
+    /// See `TopologicalServices::get_services`.
+    pub async fn get_services_default(&self) -> Result<models::Services, MyBusTrackerError> {
+        self.get_services(&self.default_operator).await
+    }
+
+    /// See `TopologicalServices::get_service_points`.
+    pub async fn get_service_points_default(
+        &self,
+        service_reference: &str,
+    ) -> Result<models::ServicePoints, MyBusTrackerError> {
+        self.get_service_points(service_reference, &self.default_operator)
+            .await
+    }
+
+    /// See `TopologicalServices::get_destinations`.
+    pub async fn get_destinations_default(
+        &self,
+    ) -> Result<models::Destinations, MyBusTrackerError> {
+        self.get_destinations(&self.default_operator).await
+    }
+
+    /// See `TopologicalServices::get_bus_stops`.
+    pub async fn get_bus_stops_default(&self) -> Result<models::BusStops, MyBusTrackerError> {
+        self.get_bus_stops(&self.default_operator).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use url::Url;
+
+    use super::*;
+    use crate::tests::{serve, serve_json, serve_with};
+    use crate::transport::MockTransport;
+
+    /// Answers `getServicePoints` requests with an empty route for the requested reference.
+    async fn serve_routes() -> Url {
+        serve_with(|request| {
+            let reference = request
+                .split(['&', ' '])
+                .find_map(|pair| pair.strip_prefix("ref="))
+                .unwrap();
+            let body = format!(
+                r#"{{"ref": "{}", "operatorId": "LB", "servicePoints": []}}"#,
+                reference
+            );
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .into_bytes()
+        })
+        .await
+    }
+
+    fn tracker(base_url: Url) -> MyBusTracker {
+        MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(base_url)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn batched_routes_are_returned_in_request_order() {
+        let tracker = tracker(serve_routes().await);
+        let references = ["26", "1", "X26", "44"];
+
+        for concurrency in [0, 1, 3, 10] {
+            let routes = tracker
+                .get_service_points_batch(&references, concurrency, &models::Operator::LothianBuses)
+                .await
+                .unwrap();
+            let returned: Vec<&str> = routes
+                .iter()
+                .map(|route| route.service_reference.as_str())
+                .collect();
+            assert_eq!(returned, references);
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_fail_if_any_request_fails() {
+        let base_url =
+            serve(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+        let error = tracker(base_url)
+            .get_service_points_batch(&["26", "1"], 2, &models::Operator::LothianBuses)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, MyBusTrackerError::CommunicationError { status: Some(404), .. }),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn default_variants_use_the_default_operator() {
+        let mut tracker = tracker(serve_json(r#"{"services": []}"#).await);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |uri| recorded.lock().unwrap().push(uri.clone()));
+
+        tracker.get_services_default().await.unwrap();
+        tracker.set_default_operator(models::Operator::LothianBuses);
+        tracker.get_services_default().await.unwrap();
+
+        let operators: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|uri| uri.query().unwrap().rsplit('&').next().unwrap().to_owned())
+            .collect();
+        assert_eq!(operators, vec!["operatorId=0", "operatorId=LB"]);
+    }
+
+    #[tokio::test]
+    async fn listed_services_request_their_own_route() {
+        let services: models::Services = serde_json::from_str(
+            r#"{"services": [{"ref": "X26", "operatorId": "LB", "mnemo": "X26",
+                "name": "Express", "dests": []}]}"#,
+        )
+        .unwrap();
+
+        let route = tracker(serve_routes().await)
+            .get_service_points_for(&services.services[0])
+            .await
+            .unwrap();
+        assert_eq!(route.service_reference, "X26");
+    }
 
-        let request = Request::new(Method::Get, uri);
+    #[tokio::test]
+    async fn topologies_record_the_topology_id_they_were_requested_under() {
+        let transport = MockTransport::new()
+            .with_response("getTopoId", r#"{"topoId": "20180101", "operatorId": "LB"}"#)
+            .with_response("getServices", r#"{"services": []}"#)
+            .with_response("getDests", r#"{"dests": []}"#)
+            .with_response("getBusStops", r#"{"busStops": []}"#);
+        let topology = MyBusTracker::builder()
+            .api_key("abc")
+            .transport(transport)
+            .build()
+            .unwrap()
+            .get_topology(&models::Operator::LothianBuses)
+            .await
+            .unwrap();
 
-        self.make_request(request)
+        assert_eq!(topology.id.topo_id, "20180101");
+        assert!(topology.services.services.is_empty());
     }
 }
@@ -0,0 +1,134 @@
+//! `tracing` Integration
+//!
+//! Each web service request is made within a `tracing` span, so that its latency can be seen in
+//! a trace viewer. Requires the `tracing` feature.
+
+use std::future::Future;
+use std::time::Instant;
+
+use hyper::Uri;
+use tracing::field::Empty;
+use tracing::Instrument;
+
+use super::MyBusTrackerError;
+
+/// Make the given request within a new `my_bus_tracker_request` span.
+///
+/// The span gives the requested API function and URI (with the API key redacted), and records
+/// the time taken in milliseconds (`elapsed_ms`) once the request, including any retries and
+/// deserialization, completes. A debug event is emitted within the span on completion, giving
+/// the error if the request failed.
+pub async fn instrument<F, T>(uri: &Uri, request: F) -> Result<T, MyBusTrackerError>
+where
+    F: Future<Output = Result<T, MyBusTrackerError>>,
+{
+    let span = tracing::debug_span!(
+        "my_bus_tracker_request",
+        function = %super::api_function(uri),
+        uri = %super::redact_uri(uri),
+        elapsed_ms = Empty,
+    );
+    let started = Instant::now();
+
+    let result = request.instrument(span.clone()).await;
+
+    let elapsed_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+    span.record("elapsed_ms", elapsed_ms);
+    match result {
+        Ok(_) => tracing::debug!(parent: &span, elapsed_ms, "Request completed"),
+        Err(ref error) => {
+            tracing::debug!(parent: &span, elapsed_ms, error = %error, "Request failed")
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::transport::MockTransport;
+    use crate::{models, MyBusTracker, TopologicalServices};
+
+    /// Collects the name and fields of each span and event, as `name field=value ...`.
+    #[derive(Clone, Default)]
+    struct Collector {
+        next_id: Arc<AtomicU64>,
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Collector {
+        fn push(&self, name: &str, fields: Fields) {
+            self.lines.lock().unwrap().push(format!("{}{}", name, fields.0));
+        }
+    }
+
+    impl Subscriber for Collector {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields(String::new());
+            span.record(&mut fields);
+            self.push(span.metadata().name(), fields);
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _span: &Id, values: &Record<'_>) {
+            let mut fields = Fields(String::new());
+            values.record(&mut fields);
+            self.push("record", fields);
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.push("event", fields);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[tokio::test]
+    async fn requests_are_made_within_a_span() {
+        let collector = Collector::default();
+        let _guard = tracing::subscriber::set_default(collector.clone());
+        let mut tracker = MyBusTracker::without_logging("abc").unwrap();
+        tracker.set_transport(MockTransport::new().with_response(
+            "getTopoId",
+            r#"{"topoId": "20180101", "operatorId": "LB"}"#,
+        ));
+
+        tracker.get_topo_id(&models::Operator::LothianBuses).await.unwrap();
+        tracker.get_services(&models::Operator::LothianBuses).await.unwrap_err();
+
+        let lines = collector.lines.lock().unwrap();
+        assert_eq!(lines.len(), 6, "{:#?}", lines);
+        assert!(lines[0].starts_with("my_bus_tracker_request function=getTopoId uri="));
+        assert!(lines[0].contains("key=REDACTED"), "{}", lines[0]);
+        assert!(lines[1].starts_with("record elapsed_ms="), "{}", lines[1]);
+        assert!(lines[2].starts_with("event message=Request completed"), "{}", lines[2]);
+        assert!(lines[3].starts_with("my_bus_tracker_request function=getServices"));
+        assert!(lines[5].contains("message=Request failed"), "{}", lines[5]);
+        assert!(lines[5].contains("404"), "{}", lines[5]);
+    }
+}
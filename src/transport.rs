@@ -0,0 +1,100 @@
+//! HTTP Transport
+//!
+//! Every web service method performs its HTTP GET requests through a `Transport`. By default
+//! this is the crate's hyper client, but any other may be set with `MyBusTracker::set_transport`,
+//! e.g. a `MockTransport` to unit test code using this crate without a network or mock server.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use hyper::Uri;
+use hyper_util::client::legacy::connect::Connect;
+
+use super::{HttpClient, MyBusTrackerError};
+
+/// The response to a `Transport` request: the response body, once received.
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, MyBusTrackerError>> + Send + 'a>>;
+
+/// A means of performing HTTP GET requests against the My Bus Tracker web service.
+pub trait Transport: Send + Sync {
+    /// Perform an HTTP GET request for the given URI, returning the response body.
+    ///
+    /// Responses with an unsuccessful status should fail with
+    /// `MyBusTrackerError::CommunicationError`, giving the status. Request timeouts, retries
+    /// and deserialization are handled by the caller.
+    fn get<'a>(&'a self, uri: &'a Uri, user_agent: &'a str) -> TransportFuture<'a>;
+}
+
+impl<C> Transport for HttpClient<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    fn get<'a>(&'a self, uri: &'a Uri, user_agent: &'a str) -> TransportFuture<'a> {
+        Box::pin(super::fetch(self, user_agent, uri.clone()))
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn get<'a>(&'a self, uri: &'a Uri, user_agent: &'a str) -> TransportFuture<'a> {
+        (**self).get(uri, user_agent)
+    }
+}
+
+/// A `Transport` answering each request with a canned response body, chosen by the requested
+/// API function (e.g. `getBusTimes`).
+///
+/// Requests for functions without a canned response fail with HTTP 404. Each requested URI is
+/// recorded, so tests can check the parameters sent. To inspect them after handing the
+/// transport to a `MyBusTracker`, set it as an `Arc<MockTransport>` and keep a clone.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: HashMap<String, Vec<u8>>,
+    requests: Mutex<Vec<Uri>>,
+}
+
+impl MockTransport {
+    /// Create a new mock transport, with no canned responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond to all requests for the given API function with the given body.
+    ///
+    /// The body must be in the tracker's response format, i.e. JSON unless set otherwise.
+    pub fn with_response<B: Into<Vec<u8>>>(mut self, function: &str, body: B) -> Self {
+        self.responses.insert(function.to_owned(), body.into());
+        self
+    }
+
+    /// The URIs requested so far, in the order they were requested.
+    pub fn requests(&self) -> Vec<Uri> {
+        match self.requests.lock() {
+            Ok(requests) => requests.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn get<'a>(&'a self, uri: &'a Uri, _user_agent: &'a str) -> TransportFuture<'a> {
+        match self.requests.lock() {
+            Ok(mut requests) => requests.push(uri.clone()),
+            Err(poisoned) => poisoned.into_inner().push(uri.clone()),
+        }
+
+        let function = super::api_function(uri);
+        let response = match self.responses.get(&function) {
+            Some(body) => Ok(body.clone()),
+            None => Err(MyBusTrackerError::CommunicationError {
+                cause: format!("HTTP 404 Not Found: no mock response for {:?}", function),
+                status: Some(404),
+                uri: None,
+                source: None,
+            }),
+        };
+        Box::pin(async move { response })
+    }
+}
@@ -0,0 +1,192 @@
+//! Watching
+//!
+//! Streams which poll a web service on a fixed interval, yielding each fresh response. Errors
+//! from an individual poll are yielded as they occur, and do not end the stream; consumers may
+//! stop on the first error with e.g. `StreamExt::take_while`.
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures_util::future;
+use futures_util::stream::{self, Stream, StreamExt};
+use hyper_util::client::legacy::connect::Connect;
+use tokio::time::{Interval, MissedTickBehavior};
+
+use super::{models, BusTimesService, DisruptionsServices, MyBusTracker, MyBusTrackerError};
+
+/// Shortest interval between polls. Shorter intervals, including zero, are raised to this.
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+impl<C> MyBusTracker<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Poll the times of every service calling at the given stop, once per `interval`.
+    ///
+    /// The first poll is made as soon as the stream is polled. Intervals shorter than one second
+    /// are treated as one second. See `get_bus_times_for_stop`.
+    pub fn watch_bus_times<'a>(
+        &'a self,
+        stop_id: &'a models::StopId,
+        departure_count: u8,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<models::BusTimes, MyBusTrackerError>> + Send + 'a {
+        poll(interval, move || {
+            self.get_bus_times_for_stop(stop_id, departure_count)
+        })
+    }
+
+    /// Poll the ongoing disruptions of all types, once per `interval`, yielding only changes.
+    ///
+    /// Successive responses are compared by disruption ID, so the first poll yields every
+    /// disruption as `DisruptionEvent::Added`. A failed poll is yielded as an error, and the
+    /// next successful poll compared against the last successful one. Intervals shorter than
+    /// one second are treated as one second. See `Disruptions::changes_since`.
+    pub fn watch_disruptions<'a>(
+        &'a self,
+        operator: &'a models::Operator,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<models::DisruptionEvent, MyBusTrackerError>> + Send + 'a {
+        let initial = models::Disruptions {
+            disruptions: Vec::new(),
+        };
+
+        poll(interval, move || async move { self.get_disruptions(&None, operator).await })
+            .scan(initial, |previous, result| {
+                let events: Vec<_> = match result {
+                    Ok(current) => {
+                        let events = current.changes_since(previous);
+                        *previous = current;
+                        events.into_iter().map(Ok).collect()
+                    }
+                    Err(e) => vec![Err(e)],
+                };
+                future::ready(Some(stream::iter(events)))
+            })
+            .flatten()
+    }
+}
+
+/// A stream of the results of calling `request` once per `interval`.
+///
+/// The interval timer is only created once the stream is first polled, so that the stream may
+/// be created outside of a Tokio runtime. If a request takes longer than the interval, the next
+/// is delayed rather than made immediately. The interval is at least `MIN_INTERVAL`.
+fn poll<T, F, Fut>(
+    interval: Duration,
+    request: F,
+) -> impl Stream<Item = Result<T, MyBusTrackerError>> + Send
+where
+    F: Fn() -> Fut + Send,
+    Fut: Future<Output = Result<T, MyBusTrackerError>> + Send,
+{
+    let interval = interval.max(MIN_INTERVAL);
+    stream::unfold(
+        (None, request),
+        move |(ticker, request): (Option<Interval>, F)| async move {
+            let mut ticker = ticker.unwrap_or_else(|| {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                ticker
+            });
+            ticker.tick().await;
+            let result = request().await;
+            Some((result, (Some(ticker), request)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use hyper::Uri;
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::tests::serve_json;
+    use crate::transport::{Transport, TransportFuture};
+
+    /// A tracker served the given body for every request, and the number of requests it makes.
+    async fn tracker(body: &str) -> (MyBusTracker, Arc<Mutex<usize>>) {
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .base_url(serve_json(body).await)
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(0));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |_| *recorded.lock().unwrap() += 1);
+        (tracker, requests)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_intervals_are_raised_to_the_minimum() {
+        let (tracker, requests) = tracker(r#"{"busTimes": []}"#).await;
+        let stop_id = models::StopId::from("36232626");
+
+        let start = Instant::now();
+        let results: Vec<_> = tracker
+            .watch_bus_times(&stop_id, 2, Duration::ZERO)
+            .take(3)
+            .collect()
+            .await;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(*requests.lock().unwrap(), 3);
+        assert!(start.elapsed() >= MIN_INTERVAL * 2, "{:?}", start.elapsed());
+    }
+
+    /// Answers every request with each of the given disruption IDs in turn, repeating the last.
+    struct ChangingDisruptions(Mutex<Vec<&'static str>>);
+
+    impl Transport for ChangingDisruptions {
+        fn get<'a>(&'a self, _uri: &'a Uri, _user_agent: &'a str) -> TransportFuture<'a> {
+            let mut ids = self.0.lock().unwrap();
+            let id = if ids.len() > 1 { ids.remove(0) } else { ids[0] };
+            let body = format!(
+                r#"{{"disruptions": [{{"id": "{}", "operatorId": "LB", "level": 3, "type": 2,
+                    "targets": ["26"], "validUntil": null, "message": "Diverted"}}]}}"#,
+                id
+            );
+            Box::pin(async move { Ok(body.into_bytes()) })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disruption_watches_yield_only_changes() {
+        let mut tracker = MyBusTracker::builder()
+            .api_key("abc")
+            .transport(ChangingDisruptions(Mutex::new(vec!["d1", "d1", "d2"])))
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(0));
+        let recorded = Arc::clone(&requests);
+        tracker.on_request(move |_| *recorded.lock().unwrap() += 1);
+        let operator = models::Operator::LothianBuses;
+
+        let mut stream = Box::pin(tracker.watch_disruptions(&operator, Duration::ZERO));
+        match stream.next().await {
+            Some(Ok(models::DisruptionEvent::Added(disruption))) => assert_eq!(disruption.id, "d1"),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(*requests.lock().unwrap(), 1);
+
+        // The second poll is unchanged, so yields nothing; the third replaces d1 with d2.
+        let start = Instant::now();
+        match stream.next().await {
+            Some(Ok(models::DisruptionEvent::Added(disruption))) => assert_eq!(disruption.id, "d2"),
+            other => panic!("{:?}", other),
+        }
+        match stream.next().await {
+            Some(Ok(models::DisruptionEvent::Removed(id))) => assert_eq!(id, "d1"),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(start.elapsed(), MIN_INTERVAL * 2);
+        assert_eq!(*requests.lock().unwrap(), 3);
+
+        // Two more unchanged polls are made before the timeout, yielding nothing.
+        assert!(tokio::time::timeout(MIN_INTERVAL * 5 / 2, stream.next()).await.is_err());
+        assert_eq!(*requests.lock().unwrap(), 5);
+    }
+}